@@ -1,24 +1,45 @@
 use crate::console;
 use crate::error::SeeedError;
-use crate::parser::Literal;
+use crate::parser::{BlockOptions, Literal};
 use crate::script::ScriptContext;
+use crate::sshclient::RunOptions;
 
-fn execute_echo(args:Vec<Literal>, _script_context: &mut ScriptContext) -> Result<(), SeeedError> {
+fn execute_echo(args:Vec<Literal>, _script_context: &mut ScriptContext) -> Result<Literal, SeeedError> {
     for arg in args {
         console::message(arg.to_string().as_str())
     }
 
-    Ok(())
+    Ok(Literal::Void)
 }
 
-fn execute_upload(args:Vec<Literal>, script_context: &mut ScriptContext) -> Result<(), SeeedError> {
+/// the options object given as third argument of `upload`, such as `{ become_user: "postgres" }`
+fn upload_options(options: Option<&Literal>, mut run_options: RunOptions) -> Result<RunOptions, SeeedError> {
+    match options {
+        None => {}
+        Some(Literal::Object(entries)) => {
+            for (name, value) in entries {
+                match (name.as_str(), value) {
+                    ("become_user", Literal::String(user)) => run_options.become_user = Some(user.clone()),
+                    ("become_user", _) => return Err(SeeedError::BadArgType("become_user option of upload must be a string".to_owned())),
+                    (name, _) => return Err(SeeedError::BadArgType(format!("unknown upload option {}", name))),
+                }
+            }
+        }
+        Some(_) => return Err(SeeedError::BadArgType("third argument of upload must be an object".to_owned())),
+    }
+
+    Ok(run_options)
+}
+
+fn execute_upload(args:Vec<Literal>, script_context: &mut ScriptContext) -> Result<Literal, SeeedError> {
 
-    if args.len() != 2 {
-        return Err(SeeedError::WrongArgCount(2, args.len()));
+    if args.len() != 2 && args.len() != 3 {
+        return Err(SeeedError::WrongArgRange(2, 3, args.len()));
     }
 
-    let source = args.get(0).ok_or(SeeedError::BadArgument("missing source argument"))?;
+    let source = args.first().ok_or(SeeedError::BadArgument("missing source argument"))?;
     let target = args.get(1).ok_or(SeeedError::BadArgument("missing target argument"))?;
+    let options = upload_options(args.get(2), script_context.run_options(&BlockOptions::default()))?;
 
     // check source type
     match source {
@@ -32,29 +53,31 @@ fn execute_upload(args:Vec<Literal>, script_context: &mut ScriptContext) -> Resu
         _ =>  return Err(SeeedError::BadArgType("second argument of upload must be a string".to_owned()))
     }
 
+    script_context.ensure_connected()?;
 
     match source {
         Literal::HereDoc(content) => {
-            script_context.ssh_client.upload(content.as_bytes(), target.to_string())?;
+            script_context.ssh_client.upload(content.as_bytes(), target.to_string(), &options)?;
         },
         Literal::String(file_path) => {
-            match std::fs::read(file_path) {
-                Ok(contents) => {   
-                    script_context.ssh_client.upload(&contents, target.to_string())?;
+            match std::fs::read(script_context.resolve_path(file_path)) {
+                Ok(contents) => {
+                    script_context.ssh_client.upload(&contents, target.to_string(), &options)?;
                 },
                 Err(e) => {
-                    println!("could not load file content: {}", e);
+                    console::error(format!("could not load file content: {}", e).as_str());
                     return Err(SeeedError::BadArgument("loading failed"))
                 }
             }
         },
         _ => return Err(SeeedError::BadArgument("could not load file content")),
     };
+    script_context.record_change();
 
-    Ok(())
+    Ok(Literal::Void)
 }
 
-fn execute_exec(args: Vec<Literal>, _script_context: &mut ScriptContext) -> Result<(), SeeedError> {
+fn execute_exec(args: Vec<Literal>, _script_context: &mut ScriptContext) -> Result<Literal, SeeedError> {
     if args.len() != 1 {
         return Err(SeeedError::WrongArgCount(1, args.len()));
     }
@@ -71,20 +94,49 @@ fn execute_exec(args: Vec<Literal>, _script_context: &mut ScriptContext) -> Resu
         return Err(SeeedError::BadArgument("Command execution failed"));
     }
 
-    Ok(())
+    Ok(Literal::Void)
 }
 
-pub fn execute_function(name: &str, args: Vec<Literal>, script_context: &mut ScriptContext) -> Result<(), SeeedError> {
+/// returns the content of a local file, relative to the script, as a heredoc so that it can be given to `upload`
+fn execute_read_file(args: Vec<Literal>, script_context: &mut ScriptContext) -> Result<Literal, SeeedError> {
+    if args.len() != 1 {
+        return Err(SeeedError::WrongArgCount(1, args.len()));
+    }
 
-    println!("Executing function: '{}'", name);
+    let file_path = match &args[0] {
+        Literal::String(file_path) => file_path,
+        _ => return Err(SeeedError::BadArgType("argument of read_file must be a string".to_owned())),
+    };
+
+    Ok(Literal::HereDoc(std::fs::read_to_string(script_context.resolve_path(file_path))?))
+}
+
+/// renders a local template file, relative to the script, with the script variables
+fn execute_template(args: Vec<Literal>, script_context: &mut ScriptContext) -> Result<Literal, SeeedError> {
+    if args.len() != 1 {
+        return Err(SeeedError::WrongArgCount(1, args.len()));
+    }
+
+    let file_path = match &args[0] {
+        Literal::String(file_path) => file_path,
+        _ => return Err(SeeedError::BadArgType("argument of template must be a string".to_owned())),
+    };
+
+    let source = std::fs::read_to_string(script_context.resolve_path(file_path))?;
+    Ok(Literal::HereDoc(script_context.resolve_template(&source)?))
+}
+
+/// signature of the built-in functions
+pub type BuiltinFunction = fn(Vec<Literal>, &mut ScriptContext) -> Result<Literal, SeeedError>;
+
+/// returns the built-in function of the given name, if any
+pub fn lookup(name: &str) -> Option<BuiltinFunction> {
     match name {
-        "echo" => execute_echo(args, script_context)?,
-        "upload" => execute_upload(args, script_context)?,
-        "exec" => execute_exec(args, script_context)?,
-        &_ => {
-            println!("Unknown function: {}", name);
-            return Err(SeeedError::UnknownFunction())
-        }
+        "echo" => Some(execute_echo),
+        "upload" => Some(execute_upload),
+        "exec" => Some(execute_exec),
+        "read_file" => Some(execute_read_file),
+        "template" => Some(execute_template),
+        &_ => None,
     }
-    Ok(())
-}
\ No newline at end of file
+}
@@ -1,17 +1,46 @@
+use std::cell::RefCell;
+
 use colored::Colorize;
 
+thread_local! {
+    /// the host whose script runs on the current thread, prefixing its output
+    static HOST: RefCell<Option<String>> = const { RefCell::new(None) };
+}
+
+/// sets the host prefixing the messages printed by the current thread,
+/// so that the output of scripts running in parallel can be told apart
+pub fn set_host(host: Option<&str>) {
+    HOST.with(|current| *current.borrow_mut() = host.map(str::to_string));
+}
+
+fn prefix() -> String {
+    HOST.with(|host| match host.borrow().as_ref() {
+        Some(host) => format!("{} ", format!("[{}]", host).cyan()),
+        None => String::new(),
+    })
+}
+
 /// log a message to the console, with a green color, and a 🌱 emoji
 /// to indicate that it is a standard log message, either from the
 /// scripting or from the system.
 pub fn log(msg: &str) {
-    println!("🌱 {}", msg.green());
+    println!("{}🌱 {}", prefix(), msg.green());
 }
 
 #[allow(dead_code)]
 pub fn error(msg: &str) {
-    println!("🚨 {}", msg.red());
+    println!("{}🚨 {}", prefix(), msg.red());
 }
 
 pub fn message(msg: &str) {
-    println!("🖥  - {}", msg.green());
-}
\ No newline at end of file
+    println!("{}🖥  - {}", prefix(), msg.green());
+}
+
+/// prints a line of the output of a remote command, in red for its standard error
+pub fn output(line: &str, stderr: bool) {
+    let line = line.trim_end_matches(['\r', '\n']);
+    match stderr {
+        true => println!("{}   | {}", prefix(), line.red()),
+        false => println!("{}   | {}", prefix(), line.yellow()),
+    }
+}
@@ -0,0 +1,174 @@
+use std::fmt;
+use std::io::{ErrorKind, Write};
+use std::path::PathBuf;
+use std::process::{Child, Command, Stdio};
+
+use crate::error::SeeedError;
+use crate::local::wait_output;
+use crate::sshclient::{CommandOutput, RemoteExecutor, RunOptions, SshOptions};
+use crate::target::Target;
+
+/// runs a script given on its standard input with the shell given as first argument,
+/// through a temporary file so that any shell or interpreter can be used
+const RUN_SCRIPT: &str = r#"script=$(mktemp) || exit 1; cat > "$script"; $1 "$script"; code=$?; rm -f "$script"; exit $code"#;
+
+/// writes the standard input to the file given as first argument
+const WRITE_FILE: &str = r#"cat > "$1""#;
+
+/// The program managing the containers, as named in the scheme of the target
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ContainerRuntime {
+    Docker,
+    Podman,
+}
+
+impl ContainerRuntime {
+
+    pub const ALL: [ContainerRuntime; 2] = [ContainerRuntime::Docker, ContainerRuntime::Podman];
+
+    /// the command line program, which is also the scheme of the targets
+    pub fn program(&self) -> &'static str {
+        match self {
+            ContainerRuntime::Docker => "docker",
+            ContainerRuntime::Podman => "podman",
+        }
+    }
+}
+
+impl fmt::Display for ContainerRuntime {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.program())
+    }
+}
+
+/// Runs the scripts in a running container, with `docker exec` or `podman exec`
+///
+/// There is no sudo in containers : the scripts run as the user of the target, or as root with
+/// `--sudo`, or as the user given by `become_user`, with the `--user` option of `exec`.
+pub struct ContainerExecutor {
+    use_sudo: bool,
+    shell: String,
+    options: SshOptions,
+    runtime: ContainerRuntime,
+    program: Option<PathBuf>,
+    container: Option<String>,
+    user: Option<String>,
+}
+
+impl ContainerExecutor {
+
+    /// builds a container executor ; only the user and timeout settings of the options are used
+    ///
+    /// the program managing the containers is the one named by the scheme of the target,
+    /// unless another program is given
+    pub fn new(use_sudo: bool, shell: String, options: SshOptions, program: Option<PathBuf>) -> Self {
+        Self {
+            use_sudo,
+            shell,
+            options,
+            runtime: ContainerRuntime::Docker,
+            program,
+            container: None,
+            user: None,
+        }
+    }
+
+    /// the program run for a runtime, given as a path or looked up in the PATH
+    fn program(&self, runtime: ContainerRuntime) -> PathBuf {
+        self.program.clone().unwrap_or(PathBuf::from(runtime.program()))
+    }
+
+    /// the user running the commands : the one to become, root with sudo, or else the user of the target
+    fn exec_user<'a>(&'a self, options: &'a RunOptions) -> Option<&'a str> {
+        let become_user = options.become_user.as_ref().or(self.options.become_user.as_ref());
+        match (become_user, options.sudo.unwrap_or(self.use_sudo)) {
+            (Some(user), _) => Some(user),
+            (None, true) => Some("root"),
+            (None, false) => self.user.as_deref(),
+        }
+    }
+
+    /// starts `<runtime> exec` with the given arguments in the container
+    fn exec(&self, user: Option<&str>, interactive: bool, args: &[&str]) -> Result<Child, SeeedError> {
+        let container = self.container.as_ref().ok_or(SeeedError::ContainerError("not connected to a container".to_string()))?;
+
+        let mut command = Command::new(self.program(self.runtime));
+        command.arg("exec");
+        if interactive {
+            command.arg("-i");
+        }
+        if let Some(user) = user {
+            command.args(["-u", user]);
+        }
+        command
+            .arg(container)
+            .args(args)
+            .stdin(if interactive { Stdio::piped() } else { Stdio::null() })
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(|e| SeeedError::ContainerError(format!("cannot run {}: {}", self.runtime, e)))
+    }
+
+    /// starts `<runtime> exec`, writing the input on its standard input
+    fn exec_with_input(&self, user: Option<&str>, args: &[&str], input: &[u8]) -> Result<Child, SeeedError> {
+        let mut child = self.exec(user, true, args)?;
+        // dropping the input closes it
+        if let Some(mut stdin) = child.stdin.take() {
+            match stdin.write_all(input) {
+                // exec failed before reading its input : its exit code and stderr tell why
+                Err(e) if e.kind() == ErrorKind::BrokenPipe => {}
+                result => result?,
+            }
+        }
+        Ok(child)
+    }
+}
+
+impl RemoteExecutor for ContainerExecutor {
+
+    fn connect(&mut self, target: &Target) -> Result<(), SeeedError> {
+        let runtime = target.runtime.ok_or(SeeedError::ContainerError(format!("{} is not a container", target)))?;
+
+        let output = Command::new(self.program(runtime))
+            .args(["inspect", "--format", "{{.State.Running}}", &target.host])
+            .output()
+            .map_err(|e| SeeedError::ContainerError(format!("cannot run {}: {}", runtime, e)))?;
+        if String::from_utf8_lossy(&output.stdout).trim() != "true" {
+            return Err(SeeedError::ContainerError(format!("container {} is not running", target.host)));
+        }
+
+        self.runtime = runtime;
+        self.container = Some(target.host.clone());
+        self.user = target.user.clone();
+        Ok(())
+    }
+
+    fn command(&self, command: &str) -> Result<CommandOutput, SeeedError> {
+        let output = self.exec(self.user.as_deref(), false, &["sh", "-c", command])?.wait_with_output()?;
+
+        Ok(CommandOutput {
+            exit_code: output.status.code().unwrap_or(-1),
+            stdout: String::from_utf8_lossy(&output.stdout).to_string(),
+            stderr: String::from_utf8_lossy(&output.stderr).to_string(),
+        })
+    }
+
+    fn run(&self, script: &str, options: &RunOptions) -> Result<CommandOutput, SeeedError> {
+        let shell = options.shell.as_ref().unwrap_or(&self.shell);
+        let child = self.exec_with_input(self.exec_user(options), &["sh", "-c", RUN_SCRIPT, "sh", shell], script.as_bytes())?;
+
+        wait_output(child, options.timeout.or(self.options.command_timeout))
+    }
+
+    fn upload(&self, content: &[u8], dst_path: String, options: &RunOptions) -> Result<(), SeeedError> {
+        let output = self.exec_with_input(self.exec_user(options), &["sh", "-c", WRITE_FILE, "sh", &dst_path], content)?.wait_with_output()?;
+
+        CommandOutput {
+            exit_code: output.status.code().unwrap_or(-1),
+            stdout: String::new(),
+            stderr: String::from_utf8_lossy(&output.stderr).to_string(),
+        }
+        .check(&format!("upload to {}", dst_path))
+    }
+}
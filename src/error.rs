@@ -9,15 +9,64 @@ pub enum SeeedError {
     #[error("SSH error")]
     SshError(#[from] ssh2::Error),
 
-    #[error("Incorrect target specified")]
+    #[error("no target specified")]
     BadTarget,
 
-    #[error("unknown function invocation")]
-    UnknownFunction(),
+    #[error("invalid target {0}")]
+    InvalidTarget(String),
+
+    #[error("could not connect to {0} within {1:?}")]
+    ConnectTimeout(String, std::time::Duration),
+
+    #[error("remote command timed out after {0:?}")]
+    CommandTimeout(std::time::Duration),
+
+    #[error("ssh config error: {0}")]
+    SshConfig(String),
+
+    #[error("inventory error: {0}")]
+    Inventory(String),
+
+    #[error("container error: {0}")]
+    ContainerError(String),
+
+    #[error("host key verification failed for {host}: the server sent {fingerprint}, which does not match the key recorded in {known_hosts}")]
+    HostKeyMismatch {
+        host: String,
+        fingerprint: String,
+        known_hosts: String,
+    },
+
+    #[error("unknown host {host} (key fingerprint {fingerprint}), add it to the known hosts file or use --host-key-check accept-new")]
+    UnknownHostKey {
+        host: String,
+        fingerprint: String,
+    },
+
+    #[error("authentication failed for user {0} (tried: {1})")]
+    AuthenticationFailed(String, String),
+
+    #[error("unknown function {0}")]
+    UnknownFunction(String),
+
+    #[error("cannot include {0}: {1}")]
+    IncludeFailed(String, std::io::Error),
+
+    #[error("include cycle: {0}")]
+    IncludeCycle(String),
+
+    #[error("return outside of a function")]
+    ReturnOutsideFunction,
+
+    #[error("too many nested function calls (more than {0})")]
+    CallDepthExceeded(usize),
 
     #[error("wrong number of arguments, expected {0}, got {1}")]
     WrongArgCount(usize, usize),
 
+    #[error("wrong number of arguments, expected {0} to {1}, got {2}")]
+    WrongArgRange(usize, usize, usize),
+
     #[error("bad argument to function call")]
     BadArgType(String),
 
@@ -27,9 +76,10 @@ pub enum SeeedError {
     #[error("undefined variable {0}")]
     UndefinedVar(String),
 
-    #[error("Parsing error at line {line}:{col}\n{line_content}\n{pointer}\n{message}")]
+    #[error("Parsing error in {file} at line {line}:{col}\n{line_content}\n{pointer}\n{message}")]
     ParseError {
         message: String,
+        file: String,
         line: usize,
         col: usize,
         line_content: String,
@@ -39,9 +89,21 @@ pub enum SeeedError {
     #[error("template error {0}")]
     Template(#[from] minijinja::Error),
 
-    #[error("can only iterate over an array")]
+    #[error("type error: {0}")]
+    TypeError(String),
+
+    #[error("division by zero")]
+    DivisionByZero,
+
+    #[error("can only iterate over an array or an object")]
     IterateOverArray,
 
+    #[error("no such key {0}")]
+    KeyNotFound(String),
+
+    #[error("index {0} is out of bounds")]
+    IndexOutOfBounds(i64),
+
     #[error("UTF-8 conversion error")]
     Utf8Error(#[from] std::string::FromUtf8Error),
     
@@ -56,4 +118,11 @@ pub enum SeeedError {
     
     #[error("Channel communication error")]
     ChannelError(String),
+
+    #[error("remote command failed with exit code {exit_code}\n{statement}\n{stderr}")]
+    RemoteCommandFailed {
+        exit_code: i32,
+        statement: String,
+        stderr: String,
+    },
 }
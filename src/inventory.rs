@@ -0,0 +1,232 @@
+use std::path::Path;
+
+use toml::{Table, Value};
+
+use crate::error::SeeedError;
+use crate::parser::Literal;
+use crate::sshconfig::wildcard_match;
+use crate::target::Target;
+
+/// name of the implicit group holding every host
+const ALL_GROUP: &str = "all";
+
+/// A host selected from the inventory, with the variables the script runs with
+#[derive(Debug, Clone)]
+pub struct InventoryHost {
+    pub name: String,
+    pub target: Target,
+    pub groups: Vec<String>,
+    pub variables: Vec<(String, Literal)>,
+}
+
+#[derive(Debug)]
+struct Host {
+    name: String,
+    target: Target,
+    variables: Table,
+}
+
+#[derive(Debug)]
+struct Group {
+    name: String,
+    hosts: Vec<String>,
+    variables: Table,
+}
+
+/// A TOML inventory of hosts, groups and variables
+///
+/// ```toml
+/// [vars]
+/// env = "production"
+///
+/// [hosts.web1]
+/// target = "deploy@10.0.0.11"
+/// http_port = 8080
+///
+/// [hosts]
+/// web2 = "deploy@10.0.0.12"
+///
+/// [groups.web]
+/// hosts = ["web1", "web2"]
+/// vars = { http_port = 80 }
+/// ```
+///
+/// A host is either a target string or a table of variables with an optional `target`, which
+/// defaults to the host name. Variables are merged from the global `vars`, then from the groups
+/// of the host in the order they are declared, then from the host itself.
+#[derive(Debug, Default)]
+pub struct Inventory {
+    variables: Table,
+    hosts: Vec<Host>,
+    groups: Vec<Group>,
+}
+
+fn invalid(message: String) -> SeeedError {
+    SeeedError::Inventory(message)
+}
+
+/// Converts a TOML value into a script literal, floats and dates becoming strings
+fn to_literal(value: &Value) -> Literal {
+    match value {
+        Value::String(value) => Literal::String(value.clone()),
+        Value::Integer(value) => Literal::Integer(*value),
+        Value::Boolean(value) => Literal::Bool(*value),
+        Value::Float(value) => Literal::String(value.to_string()),
+        Value::Datetime(value) => Literal::String(value.to_string()),
+        Value::Array(values) => Literal::Array(values.iter().map(to_literal).collect()),
+        Value::Table(table) => Literal::Object(table.iter().map(|(key, value)| (key.clone(), to_literal(value))).collect()),
+    }
+}
+
+/// Adds the variables of a table, replacing the ones already defined
+fn merge(variables: &mut Vec<(String, Literal)>, table: &Table) {
+    for (name, value) in table {
+        let literal = to_literal(value);
+        match variables.iter_mut().find(|(existing, _)| existing == name) {
+            Some((_, existing)) => *existing = literal,
+            None => variables.push((name.clone(), literal)),
+        }
+    }
+}
+
+impl Inventory {
+
+    /// Loads an inventory file
+    pub fn load(path: &Path) -> Result<Self, SeeedError> {
+        let contents = std::fs::read_to_string(path)
+            .map_err(|e| invalid(format!("cannot read {}: {}", path.display(), e)))?;
+        Self::parse(&contents)
+    }
+
+    /// Parses the content of an inventory file
+    pub fn parse(contents: &str) -> Result<Self, SeeedError> {
+        let table = contents.parse::<Table>().map_err(|e| invalid(e.to_string().trim_end().to_string()))?;
+
+        let mut inventory = Inventory::default();
+        for (key, value) in table {
+            match (key.as_str(), value) {
+                ("vars", Value::Table(variables)) => inventory.variables = variables,
+                ("hosts", Value::Table(hosts)) => {
+                    for (name, host) in hosts {
+                        inventory.hosts.push(Self::read_host(name, host)?);
+                    }
+                }
+                ("groups", Value::Table(groups)) => {
+                    for (name, group) in groups {
+                        inventory.groups.push(Self::read_group(name, group)?);
+                    }
+                }
+                ("vars" | "hosts" | "groups", _) => return Err(invalid(format!("{} must be a table", key))),
+                _ => return Err(invalid(format!("unknown section {}", key))),
+            }
+        }
+
+        // groups may be declared before their hosts
+        for group in &inventory.groups {
+            if let Some(unknown) = group.hosts.iter().find(|name| !inventory.hosts.iter().any(|host| &host.name == *name)) {
+                return Err(invalid(format!("group {} refers to unknown host {}", group.name, unknown)));
+            }
+        }
+
+        Ok(inventory)
+    }
+
+    fn read_host(name: String, value: Value) -> Result<Host, SeeedError> {
+        let (target, variables) = match value {
+            Value::String(target) => (target, Table::new()),
+            Value::Table(mut variables) => match variables.remove("target") {
+                Some(Value::String(target)) => (target, variables),
+                Some(_) => return Err(invalid(format!("the target of host {} must be a string", name))),
+                None => (name.clone(), variables),
+            },
+            _ => return Err(invalid(format!("host {} must be a target or a table", name))),
+        };
+
+        Ok(Host {
+            target: target.parse::<Target>()?,
+            name,
+            variables,
+        })
+    }
+
+    fn read_group(name: String, value: Value) -> Result<Group, SeeedError> {
+        let Value::Table(table) = value else {
+            return Err(invalid(format!("group {} must be a table", name)));
+        };
+
+        let mut group = Group {
+            name,
+            hosts: Vec::new(),
+            variables: Table::new(),
+        };
+        for (key, value) in table {
+            match (key.as_str(), value) {
+                ("hosts", Value::Array(hosts)) => {
+                    for host in hosts {
+                        match host {
+                            Value::String(host) => group.hosts.push(host),
+                            _ => return Err(invalid(format!("the hosts of group {} must be strings", group.name))),
+                        }
+                    }
+                }
+                ("vars", Value::Table(variables)) => group.variables = variables,
+                ("hosts", _) => return Err(invalid(format!("the hosts of group {} must be an array", group.name))),
+                ("vars", _) => return Err(invalid(format!("the vars of group {} must be a table", group.name))),
+                _ => return Err(invalid(format!("unknown key {} in group {}", key, group.name))),
+            }
+        }
+
+        Ok(group)
+    }
+
+    /// The groups of a host, in the order they are declared
+    fn groups_of(&self, host: &str) -> Vec<&Group> {
+        self.groups.iter().filter(|group| group.hosts.iter().any(|name| name == host)).collect()
+    }
+
+    /// Selects the hosts matching a comma separated list of host names, group names or wildcard
+    /// patterns, patterns starting with `!` excluding hosts. Every host is selected without a limit.
+    pub fn select(&self, limit: Option<&str>) -> Result<Vec<InventoryHost>, SeeedError> {
+        let patterns = limit.unwrap_or(ALL_GROUP).split(',').map(str::trim).filter(|pattern| !pattern.is_empty()).collect::<Vec<_>>();
+        let (excluded, included): (Vec<&str>, Vec<&str>) = patterns.iter().partition(|pattern| pattern.starts_with('!'));
+
+        let matches = |pattern: &str, host: &Host, groups: &[&Group]| {
+            pattern == ALL_GROUP
+                || wildcard_match(pattern, &host.name)
+                || groups.iter().any(|group| wildcard_match(pattern, &group.name))
+        };
+
+        let mut selected = Vec::new();
+        for host in &self.hosts {
+            let groups = self.groups_of(&host.name);
+            let is_included = included.is_empty() || included.iter().any(|pattern| matches(pattern, host, &groups));
+            let is_excluded = excluded.iter().any(|pattern| matches(&pattern[1..], host, &groups));
+            if !is_included || is_excluded {
+                continue;
+            }
+
+            let mut variables = Vec::new();
+            merge(&mut variables, &self.variables);
+            for group in &groups {
+                merge(&mut variables, &group.variables);
+            }
+            merge(&mut variables, &host.variables);
+
+            let group_names = groups.iter().map(|group| group.name.clone()).collect::<Vec<_>>();
+            variables.push(("inventory_hostname".to_string(), Literal::String(host.name.clone())));
+            variables.push(("group_names".to_string(), Literal::Array(group_names.iter().cloned().map(Literal::String).collect())));
+
+            selected.push(InventoryHost {
+                name: host.name.clone(),
+                target: host.target.clone(),
+                groups: group_names,
+                variables,
+            });
+        }
+
+        if selected.is_empty() {
+            return Err(invalid(format!("no host matches {}", limit.unwrap_or(ALL_GROUP))));
+        }
+        Ok(selected)
+    }
+}
@@ -4,4 +4,11 @@ pub mod parser;
 pub mod error;
 pub mod script;
 pub mod sshclient;
+pub mod local;
+pub mod container;
+pub mod sshconfig;
+pub mod target;
+pub mod inventory;
+pub mod runner;
 pub mod built_in_functions;
+pub mod operators;
@@ -0,0 +1,208 @@
+use std::io::{BufRead, BufReader, Read, Write};
+use std::os::unix::process::CommandExt;
+use std::path::PathBuf;
+use std::process::{Child, Command, Stdio};
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::console;
+use crate::error::SeeedError;
+use crate::sshclient::{shell_quote, sudo_command, CommandOutput, RemoteExecutor, RunOptions, SshOptions};
+use crate::target::Target;
+
+/// A temporary local file, removed when dropped
+struct LocalTempFile {
+    path: PathBuf,
+}
+
+impl LocalTempFile {
+    fn create(prefix: &str, content: &[u8]) -> Result<Self, SeeedError> {
+        let path = std::env::temp_dir().join(format!("{}_{}", prefix, uuid::Uuid::new_v4()));
+        std::fs::write(&path, content)?;
+        Ok(LocalTempFile { path })
+    }
+}
+
+impl Drop for LocalTempFile {
+    fn drop(&mut self) {
+        // a file moved by sudo is already gone
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+/// Runs the scripts on the local machine, without ssh
+///
+/// Remote blocks are run by the configured shell with `std::process::Command`, through sudo
+/// when running as root or as another user, and uploads are written to the local file system.
+pub struct LocalExecutor {
+    use_sudo: bool,
+    shell: String,
+    options: SshOptions,
+}
+
+impl LocalExecutor {
+
+    /// builds a local executor ; only the sudo and timeout settings of the options are used
+    pub fn new(use_sudo: bool, shell: String, options: SshOptions) -> Self {
+        Self {
+            use_sudo,
+            shell,
+            options,
+        }
+    }
+
+    fn become_user<'a>(&'a self, options: &'a RunOptions) -> Option<&'a String> {
+        options.become_user.as_ref().or(self.options.become_user.as_ref())
+    }
+
+    /// starts a shell command, through sudo when running as root or as another user,
+    /// the sudo password being written on its standard input
+    fn spawn(&self, command: &str, options: &RunOptions) -> Result<Child, SeeedError> {
+        let become_user = self.become_user(options);
+        let use_sudo = options.sudo.unwrap_or(self.use_sudo) || become_user.is_some();
+        let password = self.options.sudo_password.as_ref().filter(|_| use_sudo);
+
+        let command = match use_sudo {
+            true => sudo_command(become_user, password.is_some(), command),
+            false => command.to_string(),
+        };
+
+        let mut process = Command::new("sh");
+        process
+            .arg("-c")
+            .arg(&command)
+            .stdin(if password.is_some() { Stdio::piped() } else { Stdio::null() })
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped());
+
+        // a command that can time out gets its own process group, killed as a whole with the
+        // processes it started ; the others stay in the foreground, where sudo can prompt
+        if options.timeout.or(self.options.command_timeout).is_some() {
+            process.process_group(0);
+        }
+        let mut child = process.spawn()?;
+
+        if let (Some(password), Some(mut stdin)) = (password, child.stdin.take()) {
+            stdin.write_all(format!("{}\n", password.expose()).as_bytes())?;
+        }
+
+        Ok(child)
+    }
+}
+
+/// Reads the lines of a child output on a thread, sending them with the stream they come from
+fn forward_lines(stream: impl Read + Send + 'static, stderr: bool, sender: mpsc::Sender<(bool, String)>) {
+    thread::spawn(move || {
+        let mut reader = BufReader::new(stream);
+        let mut line = Vec::new();
+        while let Ok(n) = reader.read_until(b'\n', &mut line) {
+            if n == 0 || sender.send((stderr, String::from_utf8_lossy(&line).to_string())).is_err() {
+                break;
+            }
+            line.clear();
+        }
+    });
+}
+
+/// Kills a child process, with its whole process group when it leads one
+fn kill(child: &mut Child) {
+    let group = format!("-{}", child.id());
+    let killed = Command::new("kill").args(["-s", "KILL", "--", &group]).stderr(Stdio::null()).status();
+    if !killed.is_ok_and(|status| status.success()) {
+        let _ = child.kill();
+    }
+}
+
+/// Waits for a child process, printing its output as it comes, and builds the command output
+///
+/// The process is killed when it runs longer than the timeout, with the processes it started
+/// when it was spawned in its own process group.
+pub(crate) fn wait_output(mut child: Child, timeout: Option<Duration>) -> Result<CommandOutput, SeeedError> {
+    // the output is printed by this thread, so that it keeps the host prefix
+    let (sender, receiver) = mpsc::channel();
+    forward_lines(child.stdout.take().unwrap(), false, sender.clone());
+    forward_lines(child.stderr.take().unwrap(), true, sender);
+
+    let started = Instant::now();
+    let mut stdout = String::new();
+    let mut stderr = String::new();
+
+    loop {
+        let received = match timeout {
+            Some(timeout) => match receiver.recv_timeout(timeout.saturating_sub(started.elapsed())) {
+                Err(mpsc::RecvTimeoutError::Timeout) => {
+                    kill(&mut child);
+                    let _ = child.wait();
+                    return Err(SeeedError::CommandTimeout(timeout));
+                }
+                received => received.ok(),
+            },
+            None => receiver.recv().ok(),
+        };
+
+        // both streams are closed
+        let Some((is_stderr, line)) = received else {
+            break;
+        };
+        console::output(&line, is_stderr);
+        match is_stderr {
+            true => stderr.push_str(&line),
+            false => stdout.push_str(&line),
+        }
+    }
+
+    Ok(CommandOutput {
+        exit_code: child.wait()?.code().unwrap_or(-1),
+        stdout,
+        stderr,
+    })
+}
+
+impl RemoteExecutor for LocalExecutor {
+
+    fn connect(&mut self, _target: &Target) -> Result<(), SeeedError> {
+        Ok(())
+    }
+
+    fn command(&self, command: &str) -> Result<CommandOutput, SeeedError> {
+        let output = Command::new("sh").arg("-c").arg(command).stdin(Stdio::null()).output()?;
+
+        Ok(CommandOutput {
+            exit_code: output.status.code().unwrap_or(-1),
+            stdout: String::from_utf8_lossy(&output.stdout).to_string(),
+            stderr: String::from_utf8_lossy(&output.stderr).to_string(),
+        })
+    }
+
+    fn run(&self, script: &str, options: &RunOptions) -> Result<CommandOutput, SeeedError> {
+        let script_file = LocalTempFile::create("seeed_script", script.as_bytes())?;
+        let shell = options.shell.as_ref().unwrap_or(&self.shell);
+        let command = format!("{} {}", shell, shell_quote(&script_file.path.display().to_string()));
+        let child = self.spawn(&command, options)?;
+
+        wait_output(child, options.timeout.or(self.options.command_timeout))
+    }
+
+    fn upload(&self, content: &[u8], dst_path: String, options: &RunOptions) -> Result<(), SeeedError> {
+        if !options.sudo.unwrap_or(self.use_sudo) && self.become_user(options).is_none() {
+            std::fs::write(&dst_path, content)?;
+            return Ok(());
+        }
+
+        // the file is written as the current user, then moved or copied in place through sudo
+        let temp_file = LocalTempFile::create("seeed_upload", content)?;
+        let command = match self.become_user(options) {
+            Some(_) => format!("cp {} {}", shell_quote(&temp_file.path.display().to_string()), shell_quote(&dst_path)),
+            None => format!("mv {} {}", shell_quote(&temp_file.path.display().to_string()), shell_quote(&dst_path)),
+        };
+
+        let output = self.spawn(&command, options)?.wait_with_output()?;
+        CommandOutput {
+            exit_code: output.status.code().unwrap_or(-1),
+            stdout: String::new(),
+            stderr: String::from_utf8_lossy(&output.stderr).to_string(),
+        }
+        .check(&command)
+    }
+}
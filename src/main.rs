@@ -1,17 +1,22 @@
 use seeed::console;
-use seeed::parser;
-use seeed::error;
+use seeed::runner::{self, HostStatus, Serial};
 use seeed::script;
-use seeed::sshclient;
-use seeed::built_in_functions;
 
+use std::num::NonZeroUsize;
 use std::path::PathBuf;
+use std::time::Duration;
 use clap::Parser;
 
 
 
 use seeed::error::SeeedError;
+use seeed::container::ContainerExecutor;
+use seeed::inventory::Inventory;
+use seeed::local::LocalExecutor;
+use seeed::parser::{parse_duration, Literal};
 use seeed::script::ScriptContext;
+use seeed::target::{Target, LOCAL_HOST};
+use seeed::sshclient::{AuthMethod, HostKeyCheck, RemoteExecutor, Secret, SshOptions, DEFAULT_SHELL, PASSWORD_ENV_VAR, SUDO_PASSWORD_ENV_VAR};
 
 #[derive(clap::Parser, Debug)]
 #[clap(version, about, long_about = None)]
@@ -19,17 +24,68 @@ struct App {
     #[clap(long, short = 's', help = "use sudo to run the script", default_value_t = false, action)]
     sudo: bool,
 
-    #[clap(long, short = 't', help = "The target host to run the script on (<user>@<host>:<port>)")]
-    target: Option<String>,
+    #[clap(long, short = 't', help = "The target host to run the script on ([<user>@]<host>[:<port>], ssh://..., an ssh config alias, local, docker://<container> or podman://<container>)", conflicts_with = "inventory")]
+    target: Option<Target>,
 
-    #[clap(long, short = 'e', help = "The shell to use for the script", default_value_t = String::from("/bin/bash"))]
-    shell:String,
+    #[clap(long, help = "run the script on the local machine, without ssh (same as --target local)", default_value_t = false, action, conflicts_with_all = ["target", "inventory"])]
+    local: bool,
+
+    #[clap(long, short = 'i', help = "inventory file listing the hosts to run the script on")]
+    inventory: Option<PathBuf>,
+
+    #[clap(long, short = 'l', help = "hosts or groups of the inventory to run the script on, separated by commas", requires = "inventory")]
+    limit: Option<String>,
+
+    #[clap(long, short = 'f', help = "number of inventory hosts to run the script on at the same time", default_value = "1", requires = "inventory")]
+    forks: NonZeroUsize,
+
+    #[clap(long, help = "run the inventory hosts in successive batches of a number of hosts or a percentage of them (2, 25%...)", requires = "inventory")]
+    serial: Option<Serial>,
+
+    #[clap(long, help = "abort the run when more than this percentage of the inventory hosts failed", value_parser = clap::value_parser!(u8).range(0..=100), requires = "inventory")]
+    max_fail_percent: Option<u8>,
+
+    #[clap(long, short = 'e', help = "The shell to use for the script, defaults to /bin/bash")]
+    shell: Option<String>,
+
+    #[clap(long, short = 'u', help = "run the script as another remote user, through sudo")]
+    become_user: Option<String>,
+
+    #[clap(long, help = "prompt for the sudo password of the remote user", default_value_t = false, action)]
+    ask_sudo_password: bool,
 
     #[clap(long, short = 'd', help = "print debug information", default_value_t = false, action)]
     debug: bool,
 
     #[clap(long,  help = "load environment variables",)]
     env: Option<String>,
+
+    #[clap(long, help = "private key file used to authenticate on the target host")]
+    ssh_private_key: Option<PathBuf>,
+
+    #[clap(long, short = 'J', help = "jump hosts to connect through, separated by commas", value_delimiter = ',')]
+    jump: Vec<Target>,
+
+    #[clap(long, help = "time allowed to connect to a host (500ms, 10s, 1m...)", value_parser = parse_duration, default_value = "10s")]
+    connect_timeout: Duration,
+
+    #[clap(long, help = "interval between two keepalive messages, 0 to disable", value_parser = parse_duration, default_value = "30s")]
+    keepalive: Duration,
+
+    #[clap(long, help = "maximum duration of a remote block (500ms, 10s, 1m...)", value_parser = parse_duration)]
+    timeout: Option<Duration>,
+
+    #[clap(long, short = 'F', help = "ssh client configuration file, defaults to ~/.ssh/config")]
+    ssh_config: Option<PathBuf>,
+
+    #[clap(long, help = "authentication methods to try, in order (agent, key, password, keyboard-interactive)", value_delimiter = ',', default_value = "agent,key,password,keyboard-interactive")]
+    auth: Vec<AuthMethod>,
+
+    #[clap(long, help = "verification of the host key against the known hosts file (strict, accept-new, off)", default_value = "accept-new")]
+    host_key_check: HostKeyCheck,
+
+    #[clap(long, help = "known hosts file, defaults to ~/.ssh/known_hosts")]
+    known_hosts: Option<PathBuf>,
     
     /// Input files
     file: PathBuf,
@@ -47,12 +103,24 @@ fn main() -> Result<(), SeeedError> {
     let contents = std::fs::read_to_string(&app.file)?;
     
     // parse potential configuration headers in the script
-    let script_config = script::parse_script_headers(&contents);
+    let script_config = script::parse_script_headers(&contents)?;
 
-    let target = app.target.or(script_config.target);
     let use_sudo = app.sudo || script_config.sudo.unwrap_or(false);
-
-    if let Some(target) = &target {
+    let shell = app.shell.clone().or(script_config.shell.clone()).unwrap_or(DEFAULT_SHELL.to_string());
+
+    // the hosts to run the script on : the selected inventory hosts, or the single target
+    let hosts = match &app.inventory {
+        Some(path) => Some(Inventory::load(path)?.select(app.limit.as_deref())?),
+        None => None,
+    };
+    let target = match app.local {
+        true => Some(LOCAL_HOST.parse()?),
+        false => app.target.clone().or(script_config.target.clone()),
+    };
+
+    if let Some(hosts) = &hosts {
+        console::log(format!("running on {} hosts, {} at a time", hosts.len(), app.forks).as_str());
+    } else if let Some(target) = &target {
         console::log(format!("target is {}", target).as_str());
     } else {
         console::log("no target specified in arguments or script headers");
@@ -61,21 +129,77 @@ fn main() -> Result<(), SeeedError> {
         console::log("using sudo");
     }
 
-    let ssh_client = Box::new(seeed::sshclient::SshClient::new(use_sudo));
-    let mut script_context = ScriptContext::new(target, use_sudo, contents, ssh_client);
+    // the passwords come from the environment, then from the env file
+    let mut password = std::env::var(PASSWORD_ENV_VAR).ok();
+    let mut sudo_password = std::env::var(SUDO_PASSWORD_ENV_VAR).ok();
+    if let Some(env_file) = &app.env {
+        let mut env_variables = env_file_reader::read_file(env_file)?;
+        password = password.or(env_variables.remove(PASSWORD_ENV_VAR));
+        sudo_password = sudo_password.or(env_variables.remove(SUDO_PASSWORD_ENV_VAR));
+    }
+    if app.ask_sudo_password && sudo_password.is_none() {
+        sudo_password = Some(rpassword::prompt_password("sudo password: ")?);
+    }
+
+    let ssh_options = SshOptions {
+        identity_file: app.ssh_private_key.clone().or(script_config.identity.map(|identity| seeed::sshclient::expand_home(&identity))),
+        ssh_config_file: app.ssh_config.clone(),
+        jump_hosts: if app.jump.is_empty() { script_config.jump } else { app.jump.clone() },
+        password: password.map(Secret::new),
+        become_user: app.become_user.clone().or(script_config.become_user),
+        sudo_password: sudo_password.map(Secret::new),
+        connect_timeout: app.connect_timeout,
+        keepalive_interval: app.keepalive,
+        command_timeout: app.timeout,
+        auth_methods: app.auth.clone(),
+        host_key_check: app.host_key_check,
+        known_hosts_file: app.known_hosts.clone(),
+        // a single cache of the prompted passwords, shared by the clients of every host
+        ..SshOptions::default()
+    };
+
+    // runs the script with its own ssh session and script context
+    let run = |target: Option<Target>, variables: &[(String, Literal)]| {
+        let executor: Box<dyn RemoteExecutor> = match &target {
+            Some(target) if target.is_local() => Box::new(LocalExecutor::new(use_sudo, shell.clone(), ssh_options.clone())),
+            Some(target) if target.runtime.is_some() => Box::new(ContainerExecutor::new(use_sudo, shell.clone(), ssh_options.clone(), None)),
+            _ => Box::new(seeed::sshclient::SshClient::new(use_sudo, shell.clone(), ssh_options.clone())),
+        };
+        run_script(&app, contents.clone(), target, use_sudo, executor, variables)
+    };
+
+    let Some(hosts) = hosts else {
+        match run(target, &[]).1 {
+            Ok(_) => console::log("script completed successfully"),
+            Err(seeed_error)  => {
+                console::error(format!("script execution failed : {}", seeed_error).as_str());
+                std::process::exit(1);
+            }
+        }
+        return Ok(());
+    };
 
-    if let Some(env_file) = app.env {
-        script_context.load_env(&env_file)?;
+    let reports = runner::run_rollout(&hosts, app.forks.get(), app.serial, app.max_fail_percent, |host| run(Some(host.target.clone()), &host.variables));
+    runner::print_summary(&reports);
+    if reports.iter().any(|report| report.status != HostStatus::Ok) {
+        std::process::exit(1);
     }
 
+    Ok(())
+}
 
-    let result = script_context.run(app.debug);
-    match result {
-        Ok(_) => console::log("script completed successfully"),
-        Err(seeed_error)  => {
-            console::error(format!("script execution failed : {}", seeed_error).as_str());
+/// Runs the script on a single host, returning the number of changes made along with the result
+fn run_script(app: &App, contents: String, target: Option<Target>, use_sudo: bool, executor: Box<dyn RemoteExecutor>, variables: &[(String, Literal)]) -> (usize, Result<(), SeeedError>) {
+    let mut script_context = ScriptContext::new(target, use_sudo, contents, executor);
+    script_context.set_script_path(&app.file);
+
+    if let Some(env_file) = &app.env {
+        if let Err(seeed_error) = script_context.load_env(env_file) {
+            return (0, Err(seeed_error));
         }
     }
+    script_context.set_variables(variables);
 
-    Ok(())
+    let result = script_context.run(app.debug);
+    (script_context.changes(), result)
 }
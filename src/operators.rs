@@ -0,0 +1,175 @@
+use crate::error::SeeedError;
+use crate::parser::{BinaryOperator, Literal, UnaryOperator};
+
+/// the integer value of a literal : integers, and strings holding an integer
+/// (values loaded from an environment file are strings)
+fn as_integer(literal: &Literal) -> Option<i64> {
+    match literal {
+        Literal::Integer(i) => Some(*i),
+        Literal::String(s) | Literal::HereDoc(s) => s.trim().parse::<i64>().ok(),
+        _ => None,
+    }
+}
+
+fn as_text(literal: &Literal) -> Option<&str> {
+    match literal {
+        Literal::String(s) | Literal::HereDoc(s) => Some(s),
+        _ => None,
+    }
+}
+
+fn is_scalar(literal: &Literal) -> bool {
+    matches!(literal, Literal::String(_) | Literal::HereDoc(_) | Literal::Integer(_) | Literal::Bool(_))
+}
+
+fn type_error(operator: BinaryOperator, left: &Literal, right: &Literal) -> SeeedError {
+    SeeedError::TypeError(format!("unsupported operand types for {:?}: {:?} and {:?}", operator, left, right))
+}
+
+/// equality of two literals
+///
+/// strings and heredocs compare by content, and an integer equals a string holding the same integer
+pub fn equals(left: &Literal, right: &Literal) -> bool {
+    match (left, right) {
+        (Literal::Integer(a), b) | (b, Literal::Integer(a)) => as_integer(b) == Some(*a),
+        (Literal::Bool(a), Literal::Bool(b)) => a == b,
+        (Literal::Array(a), Literal::Array(b)) => a.len() == b.len() && a.iter().zip(b).all(|(a, b)| equals(a, b)),
+        (Literal::Object(a), Literal::Object(b)) => a.len() == b.len() && a.iter().all(|(key, value)| {
+            right.get(key).is_some_and(|other| equals(value, other))
+        }),
+        (Literal::Void, Literal::Void) => true,
+        (a, b) => match (as_text(a), as_text(b)) {
+            (Some(a), Some(b)) => a == b,
+            _ => false,
+        },
+    }
+}
+
+fn compare(operator: BinaryOperator, left: &Literal, right: &Literal) -> Result<std::cmp::Ordering, SeeedError> {
+    if let (Some(a), Some(b)) = (as_integer(left), as_integer(right)) {
+        return Ok(a.cmp(&b));
+    }
+
+    match (as_text(left), as_text(right)) {
+        (Some(a), Some(b)) => Ok(a.cmp(b)),
+        _ => Err(type_error(operator, left, right)),
+    }
+}
+
+fn arithmetic(operator: BinaryOperator, left: &Literal, right: &Literal) -> Result<Literal, SeeedError> {
+    let (a, b) = match (as_integer(left), as_integer(right)) {
+        (Some(a), Some(b)) => (a, b),
+        _ => return Err(type_error(operator, left, right)),
+    };
+
+    let result = match operator {
+        BinaryOperator::Subtract => a.checked_sub(b),
+        BinaryOperator::Multiply => a.checked_mul(b),
+        BinaryOperator::Divide | BinaryOperator::Modulo if b == 0 => return Err(SeeedError::DivisionByZero),
+        BinaryOperator::Divide => a.checked_div(b),
+        BinaryOperator::Modulo => a.checked_rem(b),
+        _ => return Err(type_error(operator, left, right)),
+    };
+
+    result.map(Literal::Integer).ok_or(SeeedError::TypeError(format!("integer overflow in {:?}", operator)))
+}
+
+fn add(left: Literal, right: Literal) -> Result<Literal, SeeedError> {
+    // integers, or strings holding one, are added like for the other arithmetic operators
+    if let (Some(a), Some(b)) = (as_integer(&left), as_integer(&right)) {
+        return a.checked_add(b)
+            .map(Literal::Integer)
+            .ok_or(SeeedError::TypeError("integer overflow in Add".to_owned()));
+    }
+
+    match (left, right) {
+        (Literal::Array(mut a), Literal::Array(b)) => {
+            a.extend(b);
+            Ok(Literal::Array(a))
+        }
+        (left, right) if is_scalar(&left) && is_scalar(&right) && (as_text(&left).is_some() || as_text(&right).is_some()) => {
+            Ok(Literal::String(format!("{}{}", left, right)))
+        }
+        (left, right) => Err(type_error(BinaryOperator::Add, &left, &right)),
+    }
+}
+
+fn contains(left: &Literal, right: &Literal) -> Result<bool, SeeedError> {
+    match right {
+        Literal::Array(items) => Ok(items.iter().any(|item| equals(left, item))),
+        Literal::Object(entries) => match as_text(left) {
+            Some(key) => Ok(entries.iter().any(|(name, _)| name == key)),
+            None => Err(type_error(BinaryOperator::In, left, right)),
+        },
+        Literal::String(s) | Literal::HereDoc(s) => match left {
+            Literal::String(_) | Literal::HereDoc(_) | Literal::Integer(_) => Ok(s.contains(left.to_string().as_str())),
+            _ => Err(type_error(BinaryOperator::In, left, right)),
+        },
+        _ => Err(type_error(BinaryOperator::In, left, right)),
+    }
+}
+
+/// applies a unary operator
+pub fn unary(operator: UnaryOperator, operand: Literal) -> Result<Literal, SeeedError> {
+    match operator {
+        UnaryOperator::Not => Ok(Literal::Bool(!operand.is_truthy())),
+        UnaryOperator::Negate => match as_integer(&operand) {
+            Some(i) => i.checked_neg()
+                .map(Literal::Integer)
+                .ok_or(SeeedError::TypeError(format!("integer overflow in {:?}", operator))),
+            None => Err(SeeedError::TypeError(format!("cannot negate {:?}", operand))),
+        },
+    }
+}
+
+/// applies a binary operator to two evaluated operands
+///
+/// (the evaluator short-circuits `&&` and `||` before reaching this point)
+pub fn binary(operator: BinaryOperator, left: Literal, right: Literal) -> Result<Literal, SeeedError> {
+    match operator {
+        BinaryOperator::Or | BinaryOperator::And => Ok(Literal::Bool(match operator {
+            BinaryOperator::Or => left.is_truthy() || right.is_truthy(),
+            _ => left.is_truthy() && right.is_truthy(),
+        })),
+        BinaryOperator::Equal => Ok(Literal::Bool(equals(&left, &right))),
+        BinaryOperator::NotEqual => Ok(Literal::Bool(!equals(&left, &right))),
+        BinaryOperator::Less => Ok(Literal::Bool(compare(operator, &left, &right)?.is_lt())),
+        BinaryOperator::LessOrEqual => Ok(Literal::Bool(compare(operator, &left, &right)?.is_le())),
+        BinaryOperator::Greater => Ok(Literal::Bool(compare(operator, &left, &right)?.is_gt())),
+        BinaryOperator::GreaterOrEqual => Ok(Literal::Bool(compare(operator, &left, &right)?.is_ge())),
+        BinaryOperator::In => Ok(Literal::Bool(contains(&left, &right)?)),
+        BinaryOperator::Add => add(left, right),
+        BinaryOperator::Subtract | BinaryOperator::Multiply | BinaryOperator::Divide | BinaryOperator::Modulo => {
+            arithmetic(operator, &left, &right)
+        }
+    }
+}
+
+/// `object.name` member access
+pub fn member(object: Literal, name: &str) -> Result<Literal, SeeedError> {
+    match &object {
+        Literal::Object(_) => object.get(name).cloned().ok_or(SeeedError::KeyNotFound(name.to_string())),
+        _ => Err(SeeedError::TypeError(format!("cannot access member {} of {:?}", name, object))),
+    }
+}
+
+/// `container[index]` access : arrays by integer index (negative indexes count from the end),
+/// objects by key
+pub fn index(container: Literal, index: Literal) -> Result<Literal, SeeedError> {
+    match (&container, &index) {
+        (Literal::Array(items), _) => {
+            let position = as_integer(&index).ok_or(SeeedError::TypeError(format!("array index must be an integer, got {:?}", index)))?;
+            let resolved = if position < 0 { position + items.len() as i64 } else { position };
+
+            usize::try_from(resolved).ok()
+                .and_then(|resolved| items.get(resolved))
+                .cloned()
+                .ok_or(SeeedError::IndexOutOfBounds(position))
+        }
+        (Literal::Object(_), _) => match as_text(&index) {
+            Some(key) => container.get(key).cloned().ok_or(SeeedError::KeyNotFound(key.to_string())),
+            None => Err(SeeedError::TypeError(format!("object key must be a string, got {:?}", index))),
+        },
+        _ => Err(SeeedError::TypeError(format!("cannot index {:?}", container))),
+    }
+}
@@ -2,12 +2,13 @@
 use std::str::{from_utf8, FromStr};
 use std::char::{decode_utf16, REPLACEMENT_CHARACTER};
 use std::fmt::{Debug, Display, Formatter};
+use std::time::Duration;
 use pom::char_class::{alpha, alphanum, hex_digit};
 use pom::Error;
 use pom::parser::{call, Parser};
 use pom::parser::{is_a, none_of,  one_of, seq, sym, list, end};
+use serde::ser::{SerializeMap, SerializeSeq};
 use serde::{Serialize, Serializer};
-use log::error;
 
 #[derive(Debug)]
 pub struct ScriptAST {
@@ -26,9 +27,14 @@ pub enum Statement {
     EmptyLine(),
     Assign(String, Expression),
     RemoteSingle(String),
-    Remote(Vec<String>),
+    Remote(BlockOptions, Vec<String>),
     FnCall(String, Vec<Expression>),
-    ForLoop(String, Expression, Vec<Statement>),
+    ForLoop(String, Option<String>, Expression, Vec<Statement>),
+    If(Vec<(Expression, Vec<Statement>)>, Option<Vec<Statement>>),
+    FnDef(String, Vec<String>, Vec<Statement>),
+    Return(Option<Expression>),
+    Include(String),
+    Sudo(Vec<Statement>),
     Error(String, usize),
 }
 
@@ -38,7 +44,54 @@ pub enum Expression {
     Variable(String),
     FnCall(String, Vec<Expression>),
     Array(Vec<Expression>),
+    Object(Vec<(String, Expression)>),
+    Member(Box<Expression>, String),
+    Index(Box<Expression>, Box<Expression>),
     HereDoc(String),
+    Remote(BlockOptions, Vec<String>),
+    Unary(UnaryOperator, Box<Expression>),
+    Binary(BinaryOperator, Box<Expression>, Box<Expression>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum UnaryOperator {
+    Not,
+    Negate,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BinaryOperator {
+    Or,
+    And,
+    Equal,
+    NotEqual,
+    Less,
+    LessOrEqual,
+    Greater,
+    GreaterOrEqual,
+    In,
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    Modulo,
+}
+
+impl BinaryOperator {
+
+    /// binding power of the operator, higher binds tighter
+    pub fn precedence(&self) -> u8 {
+        match self {
+            BinaryOperator::Or => 1,
+            BinaryOperator::And => 2,
+            BinaryOperator::Equal | BinaryOperator::NotEqual
+                | BinaryOperator::Less | BinaryOperator::LessOrEqual
+                | BinaryOperator::Greater | BinaryOperator::GreaterOrEqual
+                | BinaryOperator::In => 3,
+            BinaryOperator::Add | BinaryOperator::Subtract => 4,
+            BinaryOperator::Multiply | BinaryOperator::Divide | BinaryOperator::Modulo => 5,
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -48,9 +101,44 @@ pub enum Literal {
     Integer(i64),
     Bool(bool),
     Array(Vec<Literal>),
+    Object(Vec<(String, Literal)>),
     Void,
 }
 
+impl Literal {
+
+    /// truthiness of a literal, as used by conditions :
+    /// empty strings, zero, empty arrays, `false` and void are false, everything else is true
+    pub fn is_truthy(&self) -> bool {
+        match self {
+            Literal::HereDoc(s) | Literal::String(s) => !s.is_empty(),
+            Literal::Integer(i) => *i != 0,
+            Literal::Bool(b) => *b,
+            Literal::Array(items) => !items.is_empty(),
+            Literal::Object(entries) => !entries.is_empty(),
+            Literal::Void => false,
+        }
+    }
+
+    /// the value of a key of an object literal
+    pub fn get(&self, key: &str) -> Option<&Literal> {
+        match self {
+            Literal::Object(entries) => entries.iter().find(|(name, _)| name == key).map(|(_, value)| value),
+            _ => None,
+        }
+    }
+
+    /// sets the value of a key of an object literal, keeping the position of an existing key
+    pub fn insert(&mut self, key: String, value: Literal) {
+        if let Literal::Object(entries) = self {
+            match entries.iter_mut().find(|(name, _)| *name == key) {
+                Some(entry) => entry.1 = value,
+                None => entries.push((key, value)),
+            }
+        }
+    }
+}
+
 impl Display for Literal {
     fn fmt(&self, formatter: &mut Formatter<'_>) -> std::fmt::Result {
         match self {
@@ -66,8 +154,13 @@ impl Display for Literal {
             Literal::Bool(b) => {
                 write!(formatter, "{}", b)
             }
-            Literal::Array(_) => {
-                write!(formatter, "<<array>>")                                                      // TODO
+            Literal::Array(items) => {
+                let items = items.iter().map(|item| item.to_string()).collect::<Vec<_>>();
+                write!(formatter, "[{}]", items.join(", "))
+            }
+            Literal::Object(entries) => {
+                let entries = entries.iter().map(|(key, value)| format!("{}: {}", key, value)).collect::<Vec<_>>();
+                write!(formatter, "{{{}}}", entries.join(", "))
             }
             Literal::Void => {
                 write!(formatter, "void")
@@ -82,7 +175,102 @@ impl Serialize for Literal {
     where
         S: Serializer
     {
-        self.to_string().serialize(serializer)
+        match self {
+            Literal::Array(items) => {
+                let mut seq = serializer.serialize_seq(Some(items.len()))?;
+                for item in items {
+                    seq.serialize_element(item)?;
+                }
+                seq.end()
+            }
+            Literal::Object(entries) => {
+                let mut map = serializer.serialize_map(Some(entries.len()))?;
+                for (key, value) in entries {
+                    map.serialize_entry(key, value)?;
+                }
+                map.end()
+            }
+            Literal::Integer(i) => serializer.serialize_i64(*i),
+            Literal::Bool(b) => serializer.serialize_bool(*b),
+            Literal::Void => serializer.serialize_unit(),
+            Literal::HereDoc(s) | Literal::String(s) => serializer.serialize_str(s),
+        }
+    }
+}
+
+/// Options given on the opening line of a remote block
+///
+/// `+ retry=5 delay=3s until="systemctl is-active nginx" ignore_errors`
+/// `+ shell=python3 become_user=postgres`
+/// `+ nosudo timeout=10m`
+///
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct BlockOptions {
+    /// do not stop the script when the block fails
+    pub ignore_errors: bool,
+    /// how many times a failing block is retried
+    pub retry: Option<u32>,
+    /// the time to wait between two attempts
+    pub delay: Option<Duration>,
+    /// a remote command that must succeed for the block to be considered done
+    pub until: Option<String>,
+    /// the program running the block instead of the default shell
+    pub shell: Option<String>,
+    /// the remote user running the block, through sudo
+    pub become_user: Option<String>,
+    /// run the block with (`sudo`) or without (`nosudo`) sudo, whatever the script default
+    pub sudo: Option<bool>,
+    /// maximum duration of the block, after which it is stopped
+    pub timeout: Option<Duration>,
+}
+
+impl BlockOptions {
+
+    /// builds the block options from the raw `name[=value]` pairs found after the `+`
+    pub fn from_pairs(pairs: Vec<(String, Option<String>)>) -> Result<Self, String> {
+        let mut options = BlockOptions::default();
+
+        for (name, value) in pairs {
+            match (name.as_str(), value) {
+                ("ignore_errors", None) => options.ignore_errors = true,
+                (name @ ("sudo" | "nosudo"), None) => {
+                    if options.sudo.is_some() {
+                        return Err("Block options sudo and nosudo cannot be combined".to_owned());
+                    }
+                    options.sudo = Some(name == "sudo");
+                }
+                ("retry", Some(value)) => {
+                    let retry = value.parse::<u32>().map_err(|_| format!("Invalid retry count: {}", value))?;
+                    options.retry = Some(retry);
+                }
+                ("delay", Some(value)) => options.delay = Some(parse_duration(&value)?),
+                ("timeout", Some(value)) => options.timeout = Some(parse_duration(&value)?),
+                ("until", Some(value)) => options.until = Some(value),
+                ("shell", Some(value)) => options.shell = Some(value),
+                ("become_user", Some(value)) => options.become_user = Some(value),
+                (name @ ("ignore_errors" | "sudo" | "nosudo"), Some(_)) => return Err(format!("Block option {} does not take a value", name)),
+                (name @ ("retry" | "delay" | "timeout" | "until" | "shell" | "become_user"), None) => return Err(format!("Missing value for block option {}", name)),
+                (name, _) => return Err(format!("Unknown block option: {}", name)),
+            }
+        }
+
+        Ok(options)
+    }
+}
+
+/// Parses a duration such as `500ms`, `3s`, `2m` or `1h` (a bare number is a number of seconds)
+pub fn parse_duration(value: &str) -> Result<Duration, String> {
+    let value = value.trim();
+    let split = value.find(|c: char| !c.is_ascii_digit()).unwrap_or(value.len());
+    let (amount, unit) = value.split_at(split);
+    let amount = amount.parse::<u64>().map_err(|_| format!("Invalid duration: {}", value))?;
+
+    match unit {
+        "ms" => Ok(Duration::from_millis(amount)),
+        "" | "s" => Ok(Duration::from_secs(amount)),
+        "m" => amount.checked_mul(60).map(Duration::from_secs).ok_or_else(|| format!("Invalid duration: {}", value)),
+        "h" => amount.checked_mul(3600).map(Duration::from_secs).ok_or_else(|| format!("Invalid duration: {}", value)),
+        _ => Err(format!("Invalid duration unit: {}", value)),
     }
 }
 
@@ -133,13 +321,20 @@ fn spaces<'a>() -> Parser<'a, u8, ()> {
     one_of(b" \t").repeat(0..).discard()
 }
 
+/// blanks
+///
+/// a sequence of spaces, tabs or newlines, repeated (including zero times)
+fn blanks<'a>() -> Parser<'a, u8, ()> {
+    one_of(b" \t\r\n").repeat(0..).discard()
+}
+
 /// integer
 ///
 /// Parses an integer. The parser allow an integer to start with several 0
 /// (0000123 parses a valid integer)
 fn integer<'a>() -> Parser<'a, u8, i64> {
     let integer = one_of(b"0123456789").repeat(1..).collect().convert(from_utf8);
-    integer.convert(|v| v.parse::<i64>().or_else(Err))
+    integer.convert(|v| v.parse::<i64>())
 }
 
 /// number
@@ -147,7 +342,7 @@ fn integer<'a>() -> Parser<'a, u8, i64> {
 /// Parses a number (including floating point numbers)
 /// returns a parser that returns a f64 if parsed correctly
 fn _number<'a>() -> Parser<'a, u8, f64> {
-    let integer = one_of(b"123456789") - one_of(b"0123456789").repeat(0..) | sym(b'0');
+    let integer = (one_of(b"123456789") - one_of(b"0123456789").repeat(0..)) | sym(b'0');
     let frac = sym(b'.') + one_of(b"0123456789").repeat(1..);
     let exp = one_of(b"eE") + one_of(b"+-").opt() + one_of(b"0123456789").repeat(1..);
     let number = sym(b'-').opt() + integer + frac.opt() + exp.opt();
@@ -169,6 +364,13 @@ fn string<'a>() -> Parser<'a, u8, String> {
     string.map(|s| s.concat())
 }
 
+/// keyword
+///
+/// parses the given keyword, which must not be followed by an identifier character
+fn keyword<'a>(word: &'static [u8]) -> Parser<'a, u8, ()> {
+    (seq(word) - !(is_a(alphanum) | sym(b'_'))).discard()
+}
+
 /// identifier
 ///
 /// parse a generic identifier : starts with an alphanumeric or underscore,
@@ -207,9 +409,33 @@ fn literal_expression<'a>() -> Parser<'a, u8, Expression> {
     literal().map(Expression::Literal)
 }
 
+/// function call
+///
+/// `name(arg1, arg2, ...)`, the arguments being any expressions (including other function calls)
+fn function_call<'a>() -> Parser<'a, u8, (String, Vec<Expression>)> {
+    let args = list(call(expression), spaces() * sym(b',') * spaces());
+    identifier() - spaces() - sym(b'(') - spaces() + args - spaces() - sym(b')')
+}
+
+fn function_call_expression<'a>() -> Parser<'a, u8, Expression> {
+    function_call().map(|(name, args)| Expression::FnCall(name, args))
+}
+
+/// array expression
+///
+/// `["nginx", "git"]`, possibly spanning several lines
 fn array_expression<'a>() -> Parser<'a, u8, Expression> {
-    let elems = list(call(expression), sym(b',') * spaces());
-    (sym(b'[') * spaces() * elems - sym(b']')).map(Expression::Array)
+    let elems = list(call(expression), blanks() * sym(b',') * blanks());
+    (sym(b'[') * blanks() * elems - blanks() - sym(b',').opt() - blanks() - sym(b']')).map(Expression::Array)
+}
+
+/// object expression
+///
+/// `{ name: "web", "listen port": 8080 }`, possibly spanning several lines
+fn object_expression<'a>() -> Parser<'a, u8, Expression> {
+    let entry = (identifier() | string()) - spaces() - sym(b':') - spaces() + call(expression);
+    let entries = list(entry, blanks() * sym(b',') * blanks());
+    (sym(b'{') * blanks() * entries - blanks() - sym(b',').opt() - blanks() - sym(b'}')).map(Expression::Object)
 }
 
 fn variable_expression<'a>() -> Parser<'a, u8, Expression> {
@@ -231,8 +457,84 @@ fn heredoc_expression<'a>() -> Parser<'a, u8, Expression> {
     })
 }
 
+fn parenthesized_expression<'a>() -> Parser<'a, u8, Expression> {
+    sym(b'(') * spaces() * call(expression) - spaces() - sym(b')')
+}
+
+/// postfix access
+///
+/// `.name` member access or `[expression]` index access
+enum Accessor {
+    Member(String),
+    Index(Expression),
+}
+
+fn accessor<'a>() -> Parser<'a, u8, Accessor> {
+    let member = sym(b'.') * identifier().map(Accessor::Member);
+    let index = sym(b'[') * spaces() * call(expression).map(Accessor::Index) - spaces() - sym(b']');
+    member | index
+}
+
+fn primary_expression<'a>() -> Parser<'a, u8, Expression> {
+    let primary = function_call_expression() | literal_expression() | array_expression() | object_expression() | heredoc_expression() | variable_expression() | parenthesized_expression();
+
+    (primary + accessor().repeat(0..)).map(|(primary, accessors)| {
+        accessors.into_iter().fold(primary, |expression, accessor| match accessor {
+            Accessor::Member(name) => Expression::Member(Box::new(expression), name),
+            Accessor::Index(index) => Expression::Index(Box::new(expression), Box::new(index)),
+        })
+    })
+}
+
+fn unary_expression<'a>() -> Parser<'a, u8, Expression> {
+    let operator = sym(b'!').map(|_| UnaryOperator::Not) | sym(b'-').map(|_| UnaryOperator::Negate);
+    let unary = (operator - spaces() + call(unary_expression)).map(|(operator, operand)| Expression::Unary(operator, Box::new(operand)));
+
+    unary | primary_expression()
+}
+
+fn binary_operator<'a>() -> Parser<'a, u8, BinaryOperator> {
+    seq(b"||").map(|_| BinaryOperator::Or)
+        | seq(b"&&").map(|_| BinaryOperator::And)
+        | seq(b"==").map(|_| BinaryOperator::Equal)
+        | seq(b"!=").map(|_| BinaryOperator::NotEqual)
+        | seq(b"<=").map(|_| BinaryOperator::LessOrEqual)
+        | seq(b">=").map(|_| BinaryOperator::GreaterOrEqual)
+        | sym(b'<').map(|_| BinaryOperator::Less)
+        | sym(b'>').map(|_| BinaryOperator::Greater)
+        | keyword(b"in").map(|_| BinaryOperator::In)
+        | sym(b'+').map(|_| BinaryOperator::Add)
+        | sym(b'-').map(|_| BinaryOperator::Subtract)
+        | sym(b'*').map(|_| BinaryOperator::Multiply)
+        | sym(b'/').map(|_| BinaryOperator::Divide)
+        | sym(b'%').map(|_| BinaryOperator::Modulo)
+}
+
+/// precedence climbing
+///
+/// folds a flat `operand (operator operand)*` sequence into a tree, operators of
+/// higher precedence binding tighter and operators of same precedence associating to the left
+fn climb<I>(lhs: Expression, rest: &mut std::iter::Peekable<I>, min_precedence: u8) -> Expression
+where
+    I: Iterator<Item = (BinaryOperator, Expression)>
+{
+    let mut lhs = lhs;
+
+    while let Some((operator, mut rhs)) = rest.next_if(|(operator, _)| operator.precedence() >= min_precedence) {
+        while rest.peek().is_some_and(|(next, _)| next.precedence() > operator.precedence()) {
+            rhs = climb(rhs, rest, operator.precedence() + 1);
+        }
+        lhs = Expression::Binary(operator, Box::new(lhs), Box::new(rhs));
+    }
+
+    lhs
+}
+
 fn expression<'a>() -> Parser<'a, u8, Expression> {
-    literal_expression() | array_expression() | heredoc_expression() | variable_expression()
+    let operation = spaces() * binary_operator() - spaces() + unary_expression();
+    let parser = unary_expression() + operation.repeat(0..);
+
+    parser.map(|(first, rest)| climb(first, &mut rest.into_iter().peekable(), 0))
 }
 
 // ┌───────────────────────────────────────────────────────────────────────────────────────────┐ //
@@ -246,44 +548,138 @@ fn emptyline_statement<'a>() -> Parser<'a, u8, Statement> {
 
 fn comment_statement<'a>() -> Parser<'a, u8, Statement> {
     let till_end = none_of(b"\n").repeat(0..);
-    let comment = (sym(b'#') *  till_end.collect().convert(from_utf8).map(|_s| Statement::Comment())) - sym(b'\n');
+    let comment = (spaces() * sym(b'#') *  till_end.collect().convert(from_utf8).map(|_s| Statement::Comment())) - sym(b'\n');
     comment
 }
 
+/// the `let <name> =` start of an assignment
+fn assign_target<'a>() -> Parser<'a, u8, String> {
+    spaces() * seq(b"let") * spaces() * identifier() - spaces() - sym(b'=') - spaces()
+}
+
 fn assign_statement<'a>() -> Parser<'a, u8, Statement> {
-    let assign = spaces() * seq(b"let") * spaces() * identifier() - spaces() - sym(b'=') - spaces() + expression();
-    assign.map(|(ident, expr)| Statement::Assign(ident, expr))
+    // a captured remote block reports its invalid options like a remote block statement
+    let captured_block = (assign_target() + remote_block()).map(|(ident, ((pos, options), lines))| {
+        match BlockOptions::from_pairs(options) {
+            Ok(options) => Statement::Assign(ident, Expression::Remote(options, lines)),
+            Err(message) => Statement::Error(message, pos),
+        }
+    });
+    let assign = assign_target() + (remote_expression() | expression());
+
+    captured_block | assign.map(|(ident, expr)| Statement::Assign(ident, expr))
 }
 
 fn function_call_statement<'a>() -> Parser<'a, u8, Statement> {
-    let parser = identifier() - spaces() - sym(b'(') + list(expression(), sym(b',') + spaces()) - sym(b')');
+    let parser = spaces() * function_call();
     parser.map(|(name, args)| Statement::FnCall(name, args))
 }
 
-fn single_remote_statement<'a>() -> Parser<'a, u8, Statement> {
+fn remote_line<'a>() -> Parser<'a, u8, String> {
     let parser =  (spaces() + sym(b'|')) * none_of(b"\n").repeat(0..).collect() - sym(b'\n');
-    parser.convert(from_utf8).map(|v| Statement::RemoteSingle(v.to_owned()))
+    parser.convert(from_utf8).map(|v| v.to_owned())
 }
 
-fn multi_remote_statement<'a>() -> Parser<'a, u8, Statement> {
+/// the raw `name[=value]` options of a remote block, with their position in the script
+type RawBlockOptions = (usize, Vec<(String, Option<String>)>);
 
-    let block_start = spaces() * sym(b'+') * none_of(b"\n").repeat(0..).collect() - sym(b'\n');
-    let block_line =  (spaces() + sym(b'|')) * none_of(b"\n").repeat(0..).collect() - sym(b'\n');
+/// block option
+///
+/// a `name` flag or a `name=value` pair, the value being a string or a sequence of non blank characters
+fn block_option<'a>() -> Parser<'a, u8, (String, Option<String>)> {
+    let bare_value = none_of(b" \t\n\"").repeat(1..).convert(String::from_utf8);
+    identifier() + (sym(b'=') * (string() | bare_value)).opt()
+}
+
+/// remote block
+///
+/// a `+ <options>` line, followed by `|` lines and closed by a `+` line.
+/// returns the position of the options, the raw options and the lines
+fn remote_block<'a>() -> Parser<'a, u8, (RawBlockOptions, Vec<String>)> {
+    let options = list(block_option(), one_of(b" \t").repeat(1..));
+    let block_start = spaces() * sym(b'+') * spaces() * pos() + options - spaces() - sym(b'\n');
     let block_end = spaces() * sym(b'+') - spaces() - sym(b'\n');
 
-    let parser = block_start * block_line.convert(from_utf8).map(|s| s.to_string()).repeat(0..) - block_end;
+    block_start + remote_line().repeat(0..) - block_end
+}
+
+fn single_remote_statement<'a>() -> Parser<'a, u8, Statement> {
+    remote_line().map(Statement::RemoteSingle)
+}
+
+fn multi_remote_statement<'a>() -> Parser<'a, u8, Statement> {
+    remote_block().map(|((pos, options), lines)| {
+        match BlockOptions::from_pairs(options) {
+            Ok(options) => Statement::Remote(options, lines),
+            Err(message) => Statement::Error(message, pos),
+        }
+    })
+}
 
-    parser.map(Statement::Remote)
+/// remote expression
+///
+/// a single `|` line, whose output is captured by an assignment (captured remote blocks are
+/// parsed by the assignment, which reports their invalid options)
+fn remote_expression<'a>() -> Parser<'a, u8, Expression> {
+    remote_line().map(|line| Expression::Remote(BlockOptions::default(), vec![line]))
 }
 
 fn for_loop_statement<'a>() -> Parser<'a, u8, Statement> {
-    let loop_start_parser = spaces() * seq(b"for") * spaces() * identifier() - spaces() - seq(b"in") - spaces() + expression() - spaces() - sym(b'{');
+    let second_name = sym(b',') * spaces() * identifier() - spaces();
+    let loop_start_parser = spaces() * seq(b"for") * spaces() * identifier() - spaces() + second_name.opt() - seq(b"in") - spaces() + expression() - spaces() - sym(b'{');
     let loop_end_parser = spaces() * sym(b'}');
 
 
     let parser = loop_start_parser + call(statement).repeat(0..) - loop_end_parser;
 
-    parser.map(|((name, exp), statements)|  Statement::ForLoop(name, exp, statements))
+    parser.map(|(((name, second_name), exp), statements)|  Statement::ForLoop(name, second_name, exp, statements))
+}
+
+fn if_branch<'a>() -> Parser<'a, u8, (Expression, Vec<Statement>)> {
+    let condition = keyword(b"if") * spaces() * expression() - spaces() - sym(b'{');
+    condition + call(statement).repeat(0..) - spaces() - sym(b'}')
+}
+
+/// `else`, on the line of the closing brace or on the next ones
+fn else_keyword<'a>() -> Parser<'a, u8, ()> {
+    one_of(b" \t\n").repeat(0..) * keyword(b"else") * spaces()
+}
+
+fn if_statement<'a>() -> Parser<'a, u8, Statement> {
+    let else_if = else_keyword() * if_branch();
+    let else_branch = else_keyword() * sym(b'{') * call(statement).repeat(0..) - spaces() - sym(b'}');
+
+    let parser = spaces() * if_branch() + else_if.repeat(0..) + else_branch.opt();
+
+    parser.map(|((first, others), otherwise)| {
+        let mut branches = vec![first];
+        branches.extend(others);
+        Statement::If(branches, otherwise)
+    })
+}
+
+fn function_definition_statement<'a>() -> Parser<'a, u8, Statement> {
+    let params = list(identifier(), spaces() * sym(b',') * spaces());
+    let header = spaces() * keyword(b"fn") * spaces() * identifier() - spaces() - sym(b'(') - spaces() + params - spaces() - sym(b')') - spaces() - sym(b'{');
+    let parser = header + call(statement).repeat(0..) - spaces() - sym(b'}');
+
+    parser.map(|((name, params), statements)| Statement::FnDef(name, params, statements))
+}
+
+fn return_statement<'a>() -> Parser<'a, u8, Statement> {
+    let parser = spaces() * keyword(b"return") * spaces() * expression().opt();
+    parser.map(Statement::Return)
+}
+
+/// `sudo { ... }` : the remote blocks of the body run with sudo
+fn sudo_statement<'a>() -> Parser<'a, u8, Statement> {
+    let parser = spaces() * keyword(b"sudo") * spaces() * sym(b'{') * call(statement).repeat(0..) - spaces() - sym(b'}');
+    parser.map(Statement::Sudo)
+}
+
+fn include_statement<'a>() -> Parser<'a, u8, Statement> {
+    let parser = spaces() * keyword(b"include") * spaces() * string();
+    parser.map(Statement::Include)
 }
 
 fn pos<'a>() -> Parser<'a, u8, usize> {
@@ -292,7 +688,7 @@ fn pos<'a>() -> Parser<'a, u8, usize> {
 
 fn bad_function_call_statement<'a>() -> Parser<'a, u8, Statement> {
     let p = pos();
-    let parser = p + identifier() - spaces() - sym(b'(') + none_of(b"\n").repeat(0..).collect().convert(from_utf8) - sym(b'\n').opt();
+    let parser = spaces() * p + identifier() - spaces() - sym(b'(') + none_of(b"\n").repeat(0..).collect().convert(from_utf8) - sym(b'\n').opt();
 
     parser.map(|((pos, name), rest)| {
          Statement::Error(format!("Invalid function call syntax: {}({}", name, rest), pos)
@@ -321,7 +717,7 @@ fn error_statement<'a>() -> Parser<'a, u8, Statement> {
 
 
 fn statement<'a>() -> Parser<'a, u8, Statement> {
-    comment_statement() | emptyline_statement() | assign_statement() | function_call_statement() | single_remote_statement() | multi_remote_statement() | for_loop_statement() | bad_function_call_statement() | error_statement()
+    comment_statement() | emptyline_statement() | assign_statement() | if_statement() | function_definition_statement() | return_statement() | include_statement() | sudo_statement() | function_call_statement() | single_remote_statement() | multi_remote_statement() | for_loop_statement() | bad_function_call_statement() | error_statement()
 }
 
 // ┌───────────────────────────────────────────────────────────────────────────────────────────┐ //
@@ -0,0 +1,193 @@
+use std::str::FromStr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use colored::Colorize;
+
+use crate::console;
+use crate::error::SeeedError;
+use crate::inventory::InventoryHost;
+
+/// The outcome of the script on a host
+#[derive(Debug, Clone, PartialEq)]
+pub enum HostStatus {
+    Ok,
+    Failed(String),
+    /// not run, the rollout having been aborted
+    Skipped,
+}
+
+/// What happened on a host, as reported in the final summary
+#[derive(Debug, Clone)]
+pub struct HostReport {
+    pub name: String,
+    pub status: HostStatus,
+    /// number of remote blocks and uploads run on the host
+    pub changed: usize,
+    pub duration: Duration,
+}
+
+/// The size of the batches of a rolling execution : a number of hosts, or a percentage of them
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Serial {
+    Count(usize),
+    Percent(u8),
+}
+
+impl FromStr for Serial {
+    type Err = SeeedError;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let invalid = || SeeedError::BadArgType(format!("invalid serial {}, expected a number of hosts or a percentage", value));
+        match value.trim().strip_suffix('%') {
+            Some(percent) => match percent.trim().parse::<u8>() {
+                Ok(percent) if (1..=100).contains(&percent) => Ok(Serial::Percent(percent)),
+                _ => Err(invalid()),
+            },
+            None => match value.trim().parse::<usize>() {
+                Ok(count) if count > 0 => Ok(Serial::Count(count)),
+                _ => Err(invalid()),
+            },
+        }
+    }
+}
+
+impl Serial {
+
+    /// The number of hosts of a batch, out of the given number of hosts, a batch holding at least one host
+    pub fn batch_size(&self, hosts: usize) -> usize {
+        match self {
+            Serial::Count(count) => *count,
+            Serial::Percent(percent) => hosts * *percent as usize / 100,
+        }
+        .max(1)
+    }
+}
+
+/// Runs the script on several hosts, `forks` of them at a time
+///
+/// Each host is given to `run` on a worker thread, which builds its own script context and
+/// ssh session and returns the number of changes made along with the result of the script.
+/// The messages printed by a worker are prefixed with the name of its host. The reports are
+/// returned in the order of the hosts.
+pub fn run_hosts<F>(hosts: &[InventoryHost], forks: usize, run: F) -> Vec<HostReport>
+where
+    F: Fn(&InventoryHost) -> (usize, Result<(), SeeedError>) + Sync,
+{
+    let next = AtomicUsize::new(0);
+    let reports = Mutex::new(Vec::with_capacity(hosts.len()));
+
+    thread::scope(|scope| {
+        for _ in 0..forks.clamp(1, hosts.len().max(1)) {
+            scope.spawn(|| {
+                while let Some(host) = hosts.get(next.fetch_add(1, Ordering::SeqCst)) {
+                    console::set_host(Some(&host.name));
+                    let started = Instant::now();
+                    let (changed, result) = run(host);
+                    let status = match result {
+                        Ok(()) => {
+                            console::log("script completed successfully");
+                            HostStatus::Ok
+                        }
+                        Err(seeed_error) => {
+                            console::error(format!("script execution failed : {}", seeed_error).as_str());
+                            HostStatus::Failed(seeed_error.to_string())
+                        }
+                    };
+                    console::set_host(None);
+
+                    reports.lock().unwrap().push(HostReport {
+                        name: host.name.clone(),
+                        status,
+                        changed,
+                        duration: started.elapsed(),
+                    });
+                }
+            });
+        }
+    });
+
+    let mut reports = reports.into_inner().unwrap();
+    reports.sort_by_key(|report| hosts.iter().position(|host| host.name == report.name));
+    reports
+}
+
+/// Runs the script on several hosts in successive batches, each batch starting once the previous one is done
+///
+/// The rollout is aborted when the hosts that failed so far exceed `max_fail_percent` of all the
+/// hosts, the hosts of the remaining batches being reported as skipped. Without `serial`, all the
+/// hosts are in a single batch.
+pub fn run_rollout<F>(hosts: &[InventoryHost], forks: usize, serial: Option<Serial>, max_fail_percent: Option<u8>, run: F) -> Vec<HostReport>
+where
+    F: Fn(&InventoryHost) -> (usize, Result<(), SeeedError>) + Sync,
+{
+    let batch_size = serial.map(|serial| serial.batch_size(hosts.len())).unwrap_or(hosts.len()).max(1);
+    let batches = hosts.chunks(batch_size).collect::<Vec<_>>();
+
+    let mut reports: Vec<HostReport> = Vec::with_capacity(hosts.len());
+    for (index, batch) in batches.iter().enumerate() {
+        if serial.is_some() {
+            let names = batch.iter().map(|host| host.name.as_str()).collect::<Vec<_>>();
+            console::log(format!("batch {}/{} : {}", index + 1, batches.len(), names.join(", ")).as_str());
+        }
+        reports.extend(run_hosts(batch, forks, &run));
+
+        let Some(max_fail_percent) = max_fail_percent else {
+            continue;
+        };
+        let failed = reports.iter().filter(|report| matches!(report.status, HostStatus::Failed(_))).count();
+        // the failures exceed the allowed percentage of all the hosts
+        if failed * 100 > max_fail_percent as usize * hosts.len() && index + 1 < batches.len() {
+            console::error(format!("aborting the rollout after batch {}/{} : {} of {} hosts failed, more than {}%",
+                index + 1, batches.len(), failed, hosts.len(), max_fail_percent).as_str());
+            reports.extend(batches[index + 1..].iter().flat_map(|batch| batch.iter()).map(|host| HostReport {
+                name: host.name.clone(),
+                status: HostStatus::Skipped,
+                changed: 0,
+                duration: Duration::ZERO,
+            }));
+            break;
+        }
+    }
+
+    reports
+}
+
+/// Prints a table of the status, changes and duration of each host
+pub fn print_summary(reports: &[HostReport]) {
+    let width = reports.iter().map(|report| report.name.len()).max().unwrap_or(0).max("host".len());
+
+    println!();
+    println!("{:<width$}  {:<7}  {:>7}  {:>8}", "host", "status", "changed", "duration", width = width);
+    for report in reports {
+        let status = match report.status {
+            HostStatus::Ok => format!("{:<7}", "ok").green(),
+            HostStatus::Failed(_) => format!("{:<7}", "failed").red(),
+            HostStatus::Skipped => format!("{:<7}", "skipped").yellow(),
+        };
+        let duration = format!("{:.1}s", report.duration.as_secs_f64());
+        println!("{:<width$}  {}  {:>7}  {:>8}", report.name, status, report.changed, duration, width = width);
+    }
+
+    let hosts_with = |status: fn(&HostStatus) -> bool| {
+        reports.iter().filter(|report| status(&report.status)).map(|report| report.name.as_str()).collect::<Vec<_>>()
+    };
+    let done = hosts_with(|status| *status == HostStatus::Ok);
+    let failed = hosts_with(|status| matches!(status, HostStatus::Failed(_)));
+    let skipped = hosts_with(|status| *status == HostStatus::Skipped);
+
+    if skipped.is_empty() {
+        console::log(format!("{} ok, {} failed", done.len(), failed.len()).as_str());
+        return;
+    }
+
+    // the rollout was aborted : list the hosts in each state
+    console::error(format!("rollout aborted, {} ok, {} failed, {} skipped", done.len(), failed.len(), skipped.len()).as_str());
+    for (state, names) in [("done", done), ("failed", failed), ("skipped", skipped)] {
+        if !names.is_empty() {
+            println!("  {:<8} {}", state, names.join(", "));
+        }
+    }
+}
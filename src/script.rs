@@ -1,17 +1,40 @@
 use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::thread;
+use std::time::Duration;
 use minijinja::Environment;
 
-use crate::parser::{script_parser, Expression, Literal, Statement};
+use crate::console;
+use crate::parser::{script_parser, BinaryOperator, BlockOptions, Expression, Literal, ScriptAST, Statement};
 use crate::error::SeeedError;
 use crate::built_in_functions;
-use crate::sshclient::RemoteExecutor;
+use crate::operators;
+use crate::sshclient::{CommandOutput, RemoteExecutor, RunOptions, PASSWORD_ENV_VAR, SUDO_PASSWORD_ENV_VAR};
+use crate::target::Target;
 use regex::Regex;
 
+/// number of retries of a block having an `until` condition but no `retry` option
+const DEFAULT_UNTIL_RETRIES: u32 = 3;
+
+/// delay between two attempts of a block having no `delay` option
+const DEFAULT_RETRY_DELAY: Duration = Duration::from_secs(1);
+
+/// variables of the env file holding secrets, never defined as script variables
+const SECRET_ENV_VARS: [&str; 2] = [PASSWORD_ENV_VAR, SUDO_PASSWORD_ENV_VAR];
+
+/// maximum depth of nested user function calls
+const MAX_CALL_DEPTH: usize = 64;
+
 /// Configuration extracted from script headers
 #[derive(Debug, Default)]
 pub struct ScriptConfig {
-    pub target: Option<String>,
+    pub target: Option<Target>,
     pub sudo: Option<bool>,
+    pub identity: Option<String>,
+    pub jump: Vec<Target>,
+    pub shell: Option<String>,
+    pub become_user: Option<String>,
 }
 
 /// Parses the script content to extract configuration headers
@@ -20,13 +43,21 @@ pub struct ScriptConfig {
 /// The parsing stops at the first non-comment non-empty line.
 ///
 /// Supported headers:
-/// - `# @target: <user>@<host>:<port>`
+/// - `# @target: [<user>@]<host>[:<port>]`
 /// - `# @sudo: <true|false>`
+/// - `# @identity: <private key file>`
+/// - `# @jump: <target>[,<target>...]`
+/// - `# @shell: <shell>`
+/// - `# @become_user: <remote user>`
 ///
-pub fn parse_script_headers(content: &str) -> ScriptConfig {
+pub fn parse_script_headers(content: &str) -> Result<ScriptConfig, SeeedError> {
     let mut config = ScriptConfig::default();
     let re_target = Regex::new(r"^\s*#\s*@target:\s*(.+)$").unwrap();
     let re_sudo = Regex::new(r"^\s*#\s*@sudo:\s*(true|false)$").unwrap();
+    let re_identity = Regex::new(r"^\s*#\s*@identity:\s*(.+)$").unwrap();
+    let re_jump = Regex::new(r"^\s*#\s*@jump:\s*(.+)$").unwrap();
+    let re_shell = Regex::new(r"^\s*#\s*@shell:\s*(.+)$").unwrap();
+    let re_become_user = Regex::new(r"^\s*#\s*@become_user:\s*(.+)$").unwrap();
 
     for line in content.lines() {
         let trimmed = line.trim();
@@ -39,15 +70,122 @@ pub fn parse_script_headers(content: &str) -> ScriptConfig {
         }
 
         if let Some(captures) = re_target.captures(trimmed) {
-            config.target = Some(captures.get(1).unwrap().as_str().trim().to_string());
+            config.target = Some(captures.get(1).unwrap().as_str().parse()?);
         }
 
         if let Some(captures) = re_sudo.captures(trimmed) {
              let val = captures.get(1).unwrap().as_str();
              config.sudo = Some(val == "true");
         }
+
+        if let Some(captures) = re_identity.captures(trimmed) {
+            config.identity = Some(captures.get(1).unwrap().as_str().trim().to_string());
+        }
+
+        if let Some(captures) = re_jump.captures(trimmed) {
+            config.jump = captures.get(1).unwrap().as_str().split(',').map(str::parse).collect::<Result<_, _>>()?;
+        }
+
+        if let Some(captures) = re_shell.captures(trimmed) {
+            config.shell = Some(captures.get(1).unwrap().as_str().trim().to_string());
+        }
+
+        if let Some(captures) = re_become_user.captures(trimmed) {
+            config.become_user = Some(captures.get(1).unwrap().as_str().trim().to_string());
+        }
+    }
+    Ok(config)
+}
+
+/// Looks for the first syntax error captured by the parser, including in nested blocks
+fn find_error(statements: &[Statement]) -> Option<(&String, usize)> {
+    statements.iter().find_map(|statement| match statement {
+        Statement::Error(content, pos) => Some((content, *pos)),
+        Statement::ForLoop(_, _, _, statements) => find_error(statements),
+        Statement::If(branches, otherwise) => branches
+            .iter()
+            .find_map(|(_, statements)| find_error(statements))
+            .or_else(|| otherwise.as_ref().and_then(|statements| find_error(statements))),
+        Statement::FnDef(_, _, statements) => find_error(statements),
+        Statement::Sudo(statements) => find_error(statements),
+        _ => None,
+    })
+}
+
+/// name used in error messages for a script that was not loaded from a file
+const UNNAMED_SCRIPT: &str = "<script>";
+
+/// Parses a script, reporting syntax errors with their position in the given file
+fn parse_script(file: &str, contents: &str) -> Result<ScriptAST, SeeedError> {
+    let script = script_parser().parse(contents.as_bytes()).map_err(|e| {
+        let position = match &e {
+            pom::Error::Mismatch { position, .. } => *position,
+            pom::Error::Conversion { position, .. } => *position,
+            pom::Error::Expect { position, .. } => *position,
+            pom::Error::Incomplete => contents.len(),
+            pom::Error::Custom { position, .. } => *position,
+        };
+
+        let (current_line, current_col, line_content, pointer) = get_error_context(contents, position);
+
+        SeeedError::ParseError {
+            message: format!("{:?}", e), // pom error usually has some info
+            file: file.to_string(),
+            line: current_line,
+            col: current_col,
+            line_content,
+            pointer,
+        }
+    })?;
+
+    // Check for syntax errors captured by the parser
+    if let Some((content, pos)) = find_error(&script.statements) {
+        let (line, col, line_content, pointer) = get_error_context(contents, pos);
+        return Err(SeeedError::ParseError {
+            message: content.clone(),
+            file: file.to_string(),
+            line,
+            col,
+            line_content,
+            pointer,
+        });
+    }
+
+    Ok(script)
+}
+
+fn get_error_context(contents: &str, position: usize) -> (usize, usize, String, String) {
+    let mut current_line = 1;
+    let mut last_newline_pos = -1;
+    for (i, c) in contents.char_indices() {
+        if i >= position {
+            break;
+        }
+        if c == '\n' {
+            current_line += 1;
+            last_newline_pos = i as i64;
+        }
     }
-    config
+    let current_col = position as i64 - last_newline_pos;
+
+    let line_content = contents.lines().nth(current_line - 1).unwrap_or("").to_string();
+    let pointer = " ".repeat((current_col - 1) as usize) + "^";
+
+    (current_line, current_col as usize, line_content, pointer)
+}
+
+/// A function declared in the script with `fn name(params) { ... }`
+struct UserFunction {
+    params: Vec<String>,
+    statements: Vec<Statement>,
+}
+
+/// What to do once a statement has been executed
+enum Flow {
+    /// continue with the next statement
+    Next,
+    /// leave the current function with the given value
+    Return(Literal),
 }
 
 /// The script execution context
@@ -55,84 +193,104 @@ pub fn parse_script_headers(content: &str) -> ScriptConfig {
 /// contains :
 /// - the script content itself,
 /// - a ssh client connected to the remote host,
-/// - the defined variables and their values
+/// - the defined variables and their values, the variables local to the
+///   running user functions being stacked on top of them
+/// - the user defined functions
+///
 /// and provides a set of utility methods
 ///
 pub struct ScriptContext {
-    target: Option<String>,
+    target: Option<Target>,
     use_sudo: bool,
+    in_sudo_scope: bool,
     contents: String,
+    script_path: Option<PathBuf>,
+    includes: Vec<PathBuf>,
     variables: HashMap<String, Literal>,
+    locals: Vec<HashMap<String, Literal>>,
+    functions: HashMap<String, Rc<UserFunction>>,
     pub(crate) ssh_client: Box<dyn RemoteExecutor>,
     connected: bool,
+    /// number of remote blocks and uploads run
+    changes: usize,
+    /// print debug information, such as the built-in functions called
+    debug: bool,
 }
 
 impl ScriptContext {
 
     /// build a new script context with default parameters
     ///
-    pub fn new(target: Option<String>, use_sudo: bool, contents: String, ssh_client: Box<dyn RemoteExecutor>) -> Self {
+    pub fn new(target: Option<Target>, use_sudo: bool, contents: String, ssh_client: Box<dyn RemoteExecutor>) -> Self {
         Self {
             target,
             use_sudo,
+            in_sudo_scope: false,
             contents,
+            script_path: None,
+            includes: Vec::new(),
             variables: HashMap::new(),
+            locals: Vec::new(),
+            functions: HashMap::new(),
             ssh_client,
             connected: false,
+            changes: 0,
+            debug: false,
         }
     }
 
+    /// Sets the file the script was read from : included and read files are resolved
+    /// relative to it, and it is reported in parsing errors
+    pub fn set_script_path(&mut self, path: &Path) {
+        self.script_path = Some(path.to_path_buf());
+    }
+
     /// Loads a environment file and sets the corresponding variables
+    ///
+    /// the secrets read from the same file are not exposed to the script and its templates
     pub fn load_env(&mut self, filename: &str) -> Result<(), SeeedError> {
         let env_variables = env_file_reader::read_file(filename)?;
 
-        env_variables.iter().for_each(|(name, value)| {
+        env_variables.iter().filter(|(name, _)| !SECRET_ENV_VARS.contains(&name.as_str())).for_each(|(name, value)| {
             self.variables.insert(name.clone(), Literal::String(value.clone()));
         });
 
         Ok(())
     }
 
+    /// Sets global variables before the script runs, such as the variables of an inventory host
+    pub fn set_variables(&mut self, variables: &[(String, Literal)]) {
+        for (name, value) in variables {
+            self.variables.insert(name.clone(), value.clone());
+        }
+    }
+
+    /// Whether remote commands are run through sudo
+    pub fn use_sudo(&self) -> bool {
+        self.use_sudo
+    }
+
+    /// The number of remote blocks and uploads run so far, captured outputs excepted,
+    /// reported as the changes made to the host
+    pub fn changes(&self) -> usize {
+        self.changes
+    }
+
+    pub(crate) fn record_change(&mut self) {
+        self.changes += 1;
+    }
+
     /// Main method that runs the script
     ///
     pub fn run(&mut self, debug: bool) -> Result<(), SeeedError> {
+        self.debug = debug;
 
         // parse the script
-        let data = self.contents.as_bytes();
-        let script = script_parser().parse(data).map_err(|e| {
-            let position = match &e {
-                pom::Error::Mismatch { position, .. } => *position,
-                pom::Error::Conversion { position, .. } => *position,
-                pom::Error::Expect { position, .. } => *position,
-                pom::Error::Incomplete => self.contents.len(),
-                pom::Error::Custom { position, .. } => *position,
-            };
-            
-            let (current_line, current_col, line_content, pointer) = self.get_error_context(position);
-
-            SeeedError::ParseError {
-                message: format!("{:?}", e), // pom error usually has some info
-                line: current_line,
-                col: current_col,
-                line_content,
-                pointer,
-            }
-        })?;
-
-        // Check for syntax errors captured by the parser
-        for statement in &script.statements {
-             if let Statement::Error(content, pos) = statement {
-                 let (line, col, line_content, pointer) = self.get_error_context(*pos);
-                 return Err(SeeedError::ParseError {
-                     message: content.clone(),
-                     line,
-                     col,
-                     line_content,
-                     pointer,
-                 });
-             }
-        }
-
+        let file = match &self.script_path {
+            Some(path) => path.display().to_string(),
+            None => UNNAMED_SCRIPT.to_string(),
+        };
+        let script = parse_script(&file, &self.contents)?;
 
         // if debug flag is set,
         if debug {
@@ -149,15 +307,31 @@ impl ScriptContext {
         // No need to create directory manually, sshclient handles temp files in /tmp/
 
 
+        // register the functions first, so that they can be called before their declaration
+        for statement in &script.statements {
+            if let Statement::FnDef(name, params, statements) = statement {
+                self.define_function(name, params, statements);
+            }
+        }
+
         // execute the script
-        for statement in script.statements {
-            self.execute_statement(&statement)?;
+        match self.execute_block(&script.statements)? {
+            Flow::Next => Ok(()),
+            Flow::Return(_) => Err(SeeedError::ReturnOutsideFunction),
         }
+    }
 
-        Ok(())
+    /// executes a sequence of statements, stopping at the first `return`
+    fn execute_block(&mut self, statements: &[Statement]) -> Result<Flow, SeeedError> {
+        for statement in statements {
+            if let Flow::Return(value) = self.execute_statement(statement)? {
+                return Ok(Flow::Return(value));
+            }
+        }
+        Ok(Flow::Next)
     }
 
-    fn execute_statement(&mut self, statement: &Statement) -> Result<(), SeeedError> {
+    fn execute_statement(&mut self, statement: &Statement) -> Result<Flow, SeeedError> {
         match statement {
 
             Statement::Comment() => {
@@ -168,21 +342,26 @@ impl ScriptContext {
                 // nothing to do
             }
 
+            Statement::Assign(name, Expression::Remote(options, lines)) => {
+                // the companion variables replace any variable of the same name
+                let output = self.capture_remote(options, lines)?;
+                self.set_variable(name.clone(), Literal::String(output.stdout.trim().to_string()));
+                self.set_variable(format!("{}_stderr", name), Literal::String(output.stderr.trim().to_string()));
+                self.set_variable(format!("{}_exit_code", name), Literal::Integer(output.exit_code as i64));
+            }
             Statement::Assign(name, expression) => {
                 let literal = self.evaluate(expression)?;
-                self.variables.insert(name.clone(), literal);
+                self.set_variable(name.clone(), literal);
             }
             Statement::RemoteSingle(line) => {
                 self.ensure_connected()?;
-                let line = self.resolve_template(&line)?;
-                self.ssh_client.run(line.as_str())?;
+                let line = self.resolve_template(line)?;
+                self.ssh_client.run(line.as_str(), &self.run_options(&BlockOptions::default()))?.check(line.as_str())?;
+                self.changes += 1;
             }
-            Statement::Remote(lines) => {
-                self.ensure_connected()?;
-                let line = lines.join("\n");
-                let line = self.resolve_template(&line)?;
-
-                self.ssh_client.run(line.as_str())?;
+            Statement::Remote(options, lines) => {
+                self.capture_remote(options, lines)?;
+                self.changes += 1;
             }
             Statement::FnCall(name, args) => {
 
@@ -195,28 +374,82 @@ impl ScriptContext {
 
                 self.call_builtin_function(name, dst_args)?;
             }
-            Statement::ForLoop(varname, expression, statements) => {
+            Statement::ForLoop(first_name, second_name, expression, statements) => {
 
                 let literal = self.evaluate(expression)?;
 
-                if let Literal::Array(literals) = literal {
-                    for literal in literals {
-                        self.variables.insert(varname.clone(), literal.clone());
-                        for statement in statements {
-                            self.execute_statement(statement)?;
+                // arrays iterate over (index, value), objects over (key, value) ;
+                // with a single loop variable, it receives the value of array items and the key of object entries
+                let (items, single_is_key) = match literal {
+                    Literal::Array(literals) => {
+                        let items = literals.into_iter().enumerate().map(|(index, literal)| (Literal::Integer(index as i64), literal));
+                        (items.collect::<Vec<_>>(), false)
+                    }
+                    Literal::Object(entries) => {
+                        let items = entries.into_iter().map(|(key, value)| (Literal::String(key), value));
+                        (items.collect::<Vec<_>>(), true)
+                    }
+                    _ => {
+                        console::error(format!("cannot iterate over {:?}", expression).as_str());
+                        return Err(SeeedError::IterateOverArray)
+                    }
+                };
+
+                for (key, value) in items {
+                    match second_name {
+                        Some(second_name) => {
+                            self.set_variable(first_name.clone(), key);
+                            self.set_variable(second_name.clone(), value);
+                        }
+                        None if single_is_key => {
+                            self.set_variable(first_name.clone(), key);
+                        }
+                        None => {
+                            self.set_variable(first_name.clone(), value);
                         }
                     }
-                } else {
-                    println!("error : {:?}", expression);
-                    return Err(SeeedError::IterateOverArray)
+                    if let Flow::Return(value) = self.execute_block(statements)? {
+                        return Ok(Flow::Return(value));
+                    }
+                }
+            }
+            Statement::If(branches, otherwise) => {
+                for (condition, statements) in branches {
+                    if self.evaluate(condition)?.is_truthy() {
+                        return self.execute_block(statements);
+                    }
                 }
+
+                if let Some(statements) = otherwise {
+                    return self.execute_block(statements);
+                }
+            }
+            Statement::FnDef(name, params, statements) => {
+                self.define_function(name, params, statements);
+            }
+            Statement::Sudo(statements) => {
+                let in_sudo_scope = std::mem::replace(&mut self.in_sudo_scope, true);
+                let flow = self.execute_block(statements);
+                self.in_sudo_scope = in_sudo_scope;
+                return flow;
+            }
+            Statement::Include(path) => {
+                let path = self.resolve_template(path)?;
+                return self.include(&path);
+            }
+            Statement::Return(expression) => {
+                let value = match expression {
+                    Some(expression) => self.evaluate(expression)?,
+                    None => Literal::Void,
+                };
+                return Ok(Flow::Return(value));
             }
             Statement::Error(content, _) => {
                 // Should be unreachable if run() checks for errors first
                 panic!("Executing error statement: {}", content);
             }
         }
-        Ok(())
+        Ok(Flow::Next)
     }
 
     fn evaluate(&mut self, expression: &Expression) -> Result<Literal, SeeedError> {
@@ -233,7 +466,7 @@ impl ScriptContext {
                 }
             }
             Expression::Variable(name) => {
-                let value = self.variables.get(name);
+                let value = self.get_variable(name);
                 match value {
                     Some(value) => Ok(value.clone()),
                     None => Err(SeeedError::UndefinedVar(name.clone()))
@@ -256,15 +489,208 @@ impl ScriptContext {
 
                 Ok(Literal::Array(result))
             }
+            Expression::Object(src_entries) => {
+                let mut result = Literal::Object(vec![]);
+                for (key, exp) in src_entries {
+                    let literal = self.evaluate(exp)?;
+                    result.insert(key.clone(), literal);
+                }
+
+                Ok(result)
+            }
+            Expression::Member(object, name) => {
+                let object = self.evaluate(object)?;
+                operators::member(object, name)
+            }
+            Expression::Index(container, index) => {
+                let container = self.evaluate(container)?;
+                let index = self.evaluate(index)?;
+                operators::index(container, index)
+            }
             Expression::HereDoc(content) => {
                 Ok(Literal::HereDoc(self.resolve_template(content)?))
             }
+            Expression::Remote(options, lines) => {
+                let output = self.capture_remote(options, lines)?;
+                Ok(Literal::String(output.stdout.trim().to_string()))
+            }
+            Expression::Unary(operator, operand) => {
+                let operand = self.evaluate(operand)?;
+                operators::unary(*operator, operand)
+            }
+            Expression::Binary(operator @ (BinaryOperator::And | BinaryOperator::Or), left, right) => {
+                // short-circuit : the right operand is only evaluated when needed
+                let left = self.evaluate(left)?.is_truthy();
+                if left == (*operator == BinaryOperator::Or) {
+                    return Ok(Literal::Bool(left));
+                }
+                Ok(Literal::Bool(self.evaluate(right)?.is_truthy()))
+            }
+            Expression::Binary(operator, left, right) => {
+                let left = self.evaluate(left)?;
+                let right = self.evaluate(right)?;
+                operators::binary(*operator, left, right)
+            }
+        }
+    }
+
+    /// renders and runs a remote block, and returns its output
+    fn capture_remote(&mut self, options: &BlockOptions, lines: &[String]) -> Result<CommandOutput, SeeedError> {
+        self.ensure_connected()?;
+        let script = self.resolve_template(&lines.join("\n"))?;
+
+        self.run_remote_block(script.as_str(), options)
+    }
+
+    /// the settings of a remote run : the block options, then the `sudo { }` scope, then the script default
+    pub(crate) fn run_options(&self, options: &BlockOptions) -> RunOptions {
+        RunOptions {
+            shell: options.shell.clone(),
+            become_user: options.become_user.clone(),
+            sudo: Some(options.sudo.unwrap_or(self.in_sudo_scope || self.use_sudo)),
+            timeout: options.timeout,
+        }
+    }
+
+    /// runs a remote block, honouring its `retry`, `delay`, `until` and `ignore_errors` options
+    fn run_remote_block(&self, script: &str, options: &BlockOptions) -> Result<CommandOutput, SeeedError> {
+        let until = match &options.until {
+            Some(until) => Some(self.resolve_template(until)?),
+            None => None,
+        };
+
+        let run_options = self.run_options(options);
+        // the until condition is a command of the default shell, run by the same user
+        let until_options = RunOptions {
+            shell: None,
+            ..run_options.clone()
+        };
+
+        let default_retries = if until.is_some() { DEFAULT_UNTIL_RETRIES } else { 0 };
+        let retries = options.retry.unwrap_or(default_retries);
+        let delay = options.delay.unwrap_or(DEFAULT_RETRY_DELAY);
+
+        let mut attempt = 0;
+        loop {
+            let output = self.ssh_client.run(script, &run_options)?;
+            let result = match &until {
+                Some(until) if output.success() => self.ssh_client.run(until, &until_options)?.check(until),
+                _ => output.check(script),
+            };
+
+            match result {
+                Ok(()) => return Ok(output),
+                Err(SeeedError::RemoteCommandFailed { exit_code, .. }) if attempt < retries => {
+                    attempt += 1;
+                    console::log(format!("block failed with exit code {}, retrying in {:?} ({}/{})", exit_code, delay, attempt, retries).as_str());
+                    thread::sleep(delay);
+                }
+                Err(SeeedError::RemoteCommandFailed { exit_code, .. }) if options.ignore_errors => {
+                    console::log(format!("block failed with exit code {}, ignoring", exit_code).as_str());
+                    return Ok(output);
+                }
+                Err(error) => return Err(error),
+            }
+        }
+    }
+
+    /// resolves a local path relative to the file being executed, the included one if any
+    pub(crate) fn resolve_path(&self, path: &str) -> PathBuf {
+        let current_file = self.includes.last().or(self.script_path.as_ref());
+        match current_file.and_then(|file| file.parent()) {
+            Some(directory) => directory.join(path),
+            None => PathBuf::from(path),
+        }
+    }
+
+    /// parses and executes another script file, resolved relative to the including file
+    fn include(&mut self, path: &str) -> Result<Flow, SeeedError> {
+        let path = self.resolve_path(path);
+
+        let canonical = path.canonicalize().map_err(|e| SeeedError::IncludeFailed(path.display().to_string(), e))?;
+
+        // detect cycles against the chain of files being executed
+        let root = self.script_path.as_ref().and_then(|path| path.canonicalize().ok());
+        let chain = root.iter().chain(self.includes.iter()).collect::<Vec<_>>();
+        if chain.iter().any(|file| **file == canonical) {
+            let cycle = chain.iter().map(|file| file.display().to_string())
+                .chain(std::iter::once(canonical.display().to_string()))
+                .collect::<Vec<_>>();
+            return Err(SeeedError::IncludeCycle(cycle.join(" -> ")));
+        }
+
+        let contents = std::fs::read_to_string(&canonical).map_err(|e| SeeedError::IncludeFailed(path.display().to_string(), e))?;
+        let script = parse_script(&path.display().to_string(), &contents)?;
+
+        for statement in &script.statements {
+            if let Statement::FnDef(name, params, statements) = statement {
+                self.define_function(name, params, statements);
+            }
         }
+
+        self.includes.push(canonical);
+        let flow = self.execute_block(&script.statements);
+        self.includes.pop();
+
+        flow
     }
 
+    /// calls a built-in function, falling back to the user defined functions
     fn call_builtin_function(&mut self, name: &str, args: Vec<Literal>) -> Result<Literal, SeeedError> {
-        built_in_functions::execute_function(name, args, self)?;
-        Ok(Literal::Void)
+        if let Some(function) = built_in_functions::lookup(name) {
+            if self.debug {
+                console::log(format!("executing function {}", name).as_str());
+            }
+            return function(args, self);
+        }
+
+        if let Some(function) = self.functions.get(name).cloned() {
+            return self.call_user_function(&function, args);
+        }
+
+        console::error(format!("unknown function {}", name).as_str());
+        Err(SeeedError::UnknownFunction(name.to_string()))
+    }
+
+    /// runs a user function in a new local scope, bound to its arguments
+    fn call_user_function(&mut self, function: &UserFunction, args: Vec<Literal>) -> Result<Literal, SeeedError> {
+        if args.len() != function.params.len() {
+            return Err(SeeedError::WrongArgCount(function.params.len(), args.len()));
+        }
+
+        if self.locals.len() >= MAX_CALL_DEPTH {
+            return Err(SeeedError::CallDepthExceeded(MAX_CALL_DEPTH));
+        }
+
+        let scope = function.params.iter().cloned().zip(args).collect::<HashMap<_, _>>();
+        self.locals.push(scope);
+        let flow = self.execute_block(&function.statements);
+        self.locals.pop();
+
+        match flow? {
+            Flow::Return(value) => Ok(value),
+            Flow::Next => Ok(Literal::Void),
+        }
+    }
+
+    fn define_function(&mut self, name: &str, params: &[String], statements: &[Statement]) {
+        let function = UserFunction { params: params.to_vec(), statements: statements.to_vec() };
+        self.functions.insert(name.to_string(), Rc::new(function));
+    }
+
+    /// looks a variable up in the running function scope, then in the global variables
+    fn get_variable(&self, name: &str) -> Option<&Literal> {
+        self.locals.last()
+            .and_then(|scope| scope.get(name))
+            .or_else(|| self.variables.get(name))
+    }
+
+    /// sets a variable in the running function scope, or in the global variables outside of functions
+    fn set_variable(&mut self, name: String, value: Literal) {
+        match self.locals.last_mut() {
+            Some(scope) => scope.insert(name, value),
+            None => self.variables.insert(name, value),
+        };
     }
 
 
@@ -272,49 +698,42 @@ impl ScriptContext {
         let mut env = Environment::new();
         env.add_template("template", source)?;
         let tmpl = env.get_template("template")?;
-        let result = tmpl.render(&self.variables)?;
+        let result = match self.locals.last() {
+            Some(scope) => {
+                let mut variables = self.variables.clone();
+                variables.extend(scope.iter().map(|(name, value)| (name.clone(), value.clone())));
+                tmpl.render(&variables)?
+            }
+            None => tmpl.render(&self.variables)?,
+        };
         Ok(result)
     }
 
 
-    fn ensure_connected(&mut self) -> Result<(), SeeedError> {
+    pub(crate) fn ensure_connected(&mut self) -> Result<(), SeeedError> {
         if self.connected {
-            return Ok(());
+            if self.ssh_client.is_alive() {
+                return Ok(());
+            }
+            console::log("connection lost, reconnecting");
+            self.connected = false;
         }
 
         // Try to find target in variables if not in struct
         let target = if let Some(target) = &self.target {
             target.clone()
+        } else if let Some(target) = self.variables.get("target") {
+            target.to_string().parse::<Target>()?
         } else {
              return Err(SeeedError::BadTarget);
         };
 
-        println!("Connecting to target: {}", target);
+        console::log(format!("connecting to {}", target).as_str());
         self.ssh_client.connect(&target)?;
         self.connected = true;
         Ok(())
     }
 
-    fn get_error_context(&self, position: usize) -> (usize, usize, String, String) {
-        let mut current_line = 1;
-        let mut last_newline_pos = -1;
-        for (i, c) in self.contents.char_indices() {
-            if i >= position {
-                break;
-            }
-            if c == '\n' {
-                current_line += 1;
-                last_newline_pos = i as i64;
-            }
-        }
-        let current_col = position as i64 - last_newline_pos;
-
-        let line_content = self.contents.lines().nth(current_line - 1).unwrap_or("").to_string();
-        let pointer = " ".repeat((current_col - 1) as usize) + "^";
-        
-        (current_line, current_col as usize, line_content, pointer)
-    }
-
 }
 
 
@@ -1,16 +1,352 @@
 
 use std::io::prelude::*;
-use std::net::{TcpStream};
-use std::path::Path;
+use std::cell::Cell;
+use std::io::IsTerminal;
+use std::net::{TcpListener, TcpStream, ToSocketAddrs};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
 use std::thread;
-use colored::Colorize;
-use ssh2::Session;
+use std::time::{Duration, Instant};
+use base64::Engine;
+use ssh2::{CheckResult, HashType, KeyboardInteractivePrompt, KnownHostFileKind, Prompt, Session};
+use crate::console;
 use crate::error::SeeedError;
-use std::sync::Arc;
-use ssh2::Sftp;
+use crate::sshconfig::SshConfig;
+use crate::target::Target;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use ssh2::{Channel, Sftp};
 
 const REMOTE_TEMP_DIR: &str = "/tmp";
 
+/// shell running the remote scripts when none is given
+pub const DEFAULT_SHELL: &str = "/bin/bash";
+
+/// number of stderr lines kept to report a failing remote command
+const STDERR_TAIL_LINES: usize = 10;
+
+/// runs a script as `sh -c RUN_WRAPPER sh <pid file> <shell> <script>`, recording its process id
+/// so that it can be stopped when it times out
+const RUN_WRAPPER: &str = r#"echo $$ > "$1"; $2 "$3"; code=$?; rm -f "$1"; exit $code"#;
+
+/// stops a script started by RUN_WRAPPER, given its pid file : closing the channel does not
+/// stop the remote processes, the whole process group of the wrapper is terminated
+const KILL_SCRIPT: &str = r#"pid=$(cat "$1") || exit 0
+rm -f "$1"
+pgid=$(ps -o pgid= -p "$pid" | tr -d ' ')
+kill -TERM "-$pgid" 2>/dev/null || { pkill -TERM -P "$pid"; kill -TERM "$pid"; }"#;
+
+/// environment variable holding the passphrase of the private key file
+pub const PASSPHRASE_ENV_VAR: &str = "SEEED_SSH_PASSPHRASE";
+
+/// environment variable holding the password of the remote user
+pub const PASSWORD_ENV_VAR: &str = "SEEED_SSH_PASSWORD";
+
+/// environment variable holding the sudo password of the remote user
+pub const SUDO_PASSWORD_ENV_VAR: &str = "SEEED_SUDO_PASSWORD";
+
+/// known hosts file used when none is given
+const DEFAULT_KNOWN_HOSTS_FILE: &str = "~/.ssh/known_hosts";
+
+/// OpenSSH client configuration used when none is given
+const DEFAULT_SSH_CONFIG_FILE: &str = "~/.ssh/config";
+
+/// time allowed to open the tcp connection and to complete the ssh handshake
+pub const DEFAULT_CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// interval between two keepalive messages, so that idle connections survive NAT and firewalls
+pub const DEFAULT_KEEPALIVE_INTERVAL: Duration = Duration::from_secs(30);
+
+/// private keys tried, in this order, when no identity file is given
+const DEFAULT_IDENTITY_FILES: [&str; 3] = ["~/.ssh/id_ed25519", "~/.ssh/id_ecdsa", "~/.ssh/id_rsa"];
+
+/// libssh2 error code returned when a blocking call exceeds the session timeout
+const LIBSSH2_ERROR_TIMEOUT: i32 = -9;
+
+/// libssh2 error code returned when a private key file cannot be decoded,
+/// usually because it is protected by a passphrase
+const LIBSSH2_ERROR_FILE: i32 = -16;
+
+/// An SSH authentication method
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AuthMethod {
+    /// identities of the running ssh agent
+    Agent,
+    /// a private key file
+    PublicKey,
+    /// the password of the remote user
+    Password,
+    /// a keyboard-interactive challenge, answered with the password
+    KeyboardInteractive,
+}
+
+impl FromStr for AuthMethod {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.trim() {
+            "agent" => Ok(AuthMethod::Agent),
+            "key" => Ok(AuthMethod::PublicKey),
+            "password" => Ok(AuthMethod::Password),
+            "keyboard-interactive" => Ok(AuthMethod::KeyboardInteractive),
+            other => Err(format!("unknown authentication method {}", other)),
+        }
+    }
+}
+
+impl std::fmt::Display for AuthMethod {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AuthMethod::Agent => write!(f, "agent"),
+            AuthMethod::PublicKey => write!(f, "key"),
+            AuthMethod::Password => write!(f, "password"),
+            AuthMethod::KeyboardInteractive => write!(f, "keyboard-interactive"),
+        }
+    }
+}
+
+/// How the host key sent by the server is verified against the known hosts file
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum HostKeyCheck {
+    /// the host must already be known, with the same key
+    Strict,
+    /// unknown hosts are added to the known hosts file, known hosts must match
+    AcceptNew,
+    /// no verification
+    Off,
+}
+
+impl FromStr for HostKeyCheck {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.trim() {
+            "strict" => Ok(HostKeyCheck::Strict),
+            "accept-new" => Ok(HostKeyCheck::AcceptNew),
+            "off" => Ok(HostKeyCheck::Off),
+            other => Err(format!("unknown host key check mode {}", other)),
+        }
+    }
+}
+
+/// The `SHA256:...` fingerprint of a host key, as displayed by OpenSSH
+pub fn fingerprint(hash: &[u8]) -> String {
+    format!("SHA256:{}", base64::engine::general_purpose::STANDARD_NO_PAD.encode(hash))
+}
+
+/// A sensitive value that is never displayed, even in debug output
+#[derive(Clone, PartialEq)]
+pub struct Secret(String);
+
+impl Secret {
+    pub fn new(value: String) -> Self {
+        Self(value)
+    }
+
+    pub fn expose(&self) -> &str {
+        &self.0
+    }
+}
+
+impl std::fmt::Debug for Secret {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "***")
+    }
+}
+
+/// The passwords and passphrases prompted for, shared by the ssh clients of a run
+///
+/// The prompts are made one at a time, the other clients waiting for them and reusing the answers,
+/// so that hosts running in parallel do not prompt on the same terminal at once.
+#[derive(Debug, Default)]
+pub struct Prompted {
+    /// accepted passwords by `user@host`
+    passwords: HashMap<String, Secret>,
+    /// passphrases by private key file
+    passphrases: HashMap<PathBuf, Secret>,
+}
+
+/// Answers every keyboard-interactive prompt with the password
+struct PasswordPrompt<'a> {
+    password: &'a Secret,
+}
+
+impl KeyboardInteractivePrompt for PasswordPrompt<'_> {
+    fn prompt<'a>(&mut self, _username: &str, _instructions: &str, prompts: &[Prompt<'a>]) -> Vec<String> {
+        prompts.iter().map(|_| self.password.expose().to_string()).collect()
+    }
+}
+
+/// Settings used to open and authenticate the ssh session
+#[derive(Debug, Clone)]
+pub struct SshOptions {
+    /// private key file, the keys of the ssh config or the default keys of `~/.ssh` are tried when not set
+    pub identity_file: Option<PathBuf>,
+    /// the OpenSSH client configuration, `~/.ssh/config` when not set
+    pub ssh_config_file: Option<PathBuf>,
+    /// hosts to connect through, in order, before reaching the target
+    pub jump_hosts: Vec<Target>,
+    /// password of the remote user, prompted for when needed and not set
+    pub password: Option<Secret>,
+    /// authentication methods, tried in order until one succeeds
+    pub auth_methods: Vec<AuthMethod>,
+    /// the remote user running the scripts, through sudo, unless a block gives another one
+    pub become_user: Option<String>,
+    /// the password asked by sudo on the remote host, none when sudo needs no password
+    pub sudo_password: Option<Secret>,
+    /// time allowed to connect and to complete the ssh handshake
+    pub connect_timeout: Duration,
+    /// interval between two keepalive messages, no keepalive when zero
+    pub keepalive_interval: Duration,
+    /// maximum duration of a remote block, unless it has a `timeout` option
+    pub command_timeout: Option<Duration>,
+    /// verification of the server host key
+    pub host_key_check: HostKeyCheck,
+    /// the known hosts file, `~/.ssh/known_hosts` when not set
+    pub known_hosts_file: Option<PathBuf>,
+    /// the answers to the prompts, shared by the clones of the options
+    pub prompted: Arc<Mutex<Prompted>>,
+}
+
+impl Default for SshOptions {
+    fn default() -> Self {
+        Self {
+            identity_file: None,
+            ssh_config_file: None,
+            jump_hosts: Vec::new(),
+            password: None,
+            become_user: None,
+            sudo_password: None,
+            connect_timeout: DEFAULT_CONNECT_TIMEOUT,
+            keepalive_interval: DEFAULT_KEEPALIVE_INTERVAL,
+            command_timeout: None,
+            auth_methods: vec![AuthMethod::Agent, AuthMethod::PublicKey, AuthMethod::Password, AuthMethod::KeyboardInteractive],
+            host_key_check: HostKeyCheck::AcceptNew,
+            known_hosts_file: None,
+            prompted: Arc::new(Mutex::new(Prompted::default())),
+        }
+    }
+}
+
+/// Expands a leading `~/` to the home directory of the current user
+pub fn expand_home(path: &str) -> PathBuf {
+    match (path.strip_prefix("~/"), std::env::var_os("HOME")) {
+        (Some(rest), Some(home)) => PathBuf::from(home).join(rest),
+        _ => PathBuf::from(path),
+    }
+}
+
+/// A host to open a session to, resolved from a target and the ssh config
+struct Endpoint {
+    host: String,
+    port: u16,
+    username: String,
+    identity_files: Vec<PathBuf>,
+    proxy_jump: Option<String>,
+}
+
+/// A duration as the milliseconds of a libssh2 timeout, clamped to the largest one
+fn timeout_millis(duration: Duration) -> u32 {
+    u32::try_from(duration.as_millis()).unwrap_or(u32::MAX)
+}
+
+/// Opens a tcp connection, trying each address of the host in turn
+fn connect_tcp(host: &str, port: u16, timeout: Duration) -> Result<TcpStream, SeeedError> {
+    let mut last_error = std::io::Error::new(std::io::ErrorKind::NotFound, format!("no address found for {}", host));
+
+    for address in (host, port).to_socket_addrs()? {
+        match TcpStream::connect_timeout(&address, timeout) {
+            Ok(stream) => return Ok(stream),
+            Err(e) => last_error = e,
+        }
+    }
+
+    match last_error.kind() {
+        std::io::ErrorKind::TimedOut => Err(SeeedError::ConnectTimeout(format!("{}:{}", host, port), timeout)),
+        _ => Err(SeeedError::IoError(last_error)),
+    }
+}
+
+/// Opens a `direct-tcpip` channel from a jump host to `host:port`, and exposes it as a local tcp stream
+///
+/// The second session needs a socket : the channel is bridged to a loopback connection by a thread,
+/// which owns the jump host session and stops when either side is closed.
+fn tunnel(jump_session: Session, host: &str, port: u16) -> Result<TcpStream, SeeedError> {
+    let channel = jump_session.channel_direct_tcpip(host, port, None)?;
+
+    let listener = TcpListener::bind("127.0.0.1:0")?;
+    let stream = TcpStream::connect(listener.local_addr()?)?;
+
+    // only accept our own connection, not one of another local process
+    let local_address = stream.local_addr()?;
+    let forwarded = loop {
+        let (forwarded, peer_address) = listener.accept()?;
+        if peer_address == local_address {
+            break forwarded;
+        }
+    };
+
+    thread::spawn(move || {
+        if let Err(e) = forward(&jump_session, channel, forwarded) {
+            log::debug!("jump host tunnel closed: {}", e);
+        }
+    });
+
+    Ok(stream)
+}
+
+/// Copies data both ways between a channel and a socket until one of them is closed
+fn forward(session: &Session, mut channel: Channel, mut stream: TcpStream) -> std::io::Result<()> {
+    session.set_blocking(false);
+    stream.set_nonblocking(true)?;
+
+    let mut buff = [0u8; 16384];
+    loop {
+        let mut made_progress = false;
+
+        match stream.read(&mut buff) {
+            Ok(0) => break,
+            Ok(n) => {
+                write_all_nonblocking(&mut channel, &buff[..n])?;
+                made_progress = true;
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => {}
+            Err(e) => return Err(e),
+        }
+
+        match channel.read(&mut buff) {
+            Ok(0) if channel.eof() => break,
+            Ok(0) => {}
+            Ok(n) => {
+                write_all_nonblocking(&mut stream, &buff[..n])?;
+                made_progress = true;
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => {}
+            Err(e) => return Err(e),
+        }
+
+        if !made_progress {
+            let _ = session.keepalive_send();
+            thread::sleep(std::time::Duration::from_millis(1));
+        }
+    }
+
+    let _ = channel.close();
+    Ok(())
+}
+
+fn write_all_nonblocking(writer: &mut impl Write, mut data: &[u8]) -> std::io::Result<()> {
+    while !data.is_empty() {
+        match writer.write(data) {
+            Ok(0) => return Err(std::io::ErrorKind::WriteZero.into()),
+            Ok(n) => data = &data[n..],
+            Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => thread::sleep(std::time::Duration::from_millis(1)),
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(())
+}
+
 /// RAII guard for remote temporary files
 struct RemoteTempFile {
     sftp: Arc<Sftp>,
@@ -32,112 +368,418 @@ impl Drop for RemoteTempFile {
     }
 }
 
+/// The outcome of a command or script run on the remote target
+#[derive(Debug, Clone, Default)]
+pub struct CommandOutput {
+    pub exit_code: i32,
+    pub stdout: String,
+    pub stderr: String,
+}
+
+impl CommandOutput {
+
+    pub fn success(&self) -> bool {
+        self.exit_code == 0
+    }
+
+    /// turns a non-zero exit code into a `SeeedError::RemoteCommandFailed`
+    /// reporting the statement and the last lines of stderr
+    pub fn check(&self, statement: &str) -> Result<(), SeeedError> {
+        if self.success() {
+            return Ok(());
+        }
+
+        let lines = self.stderr.lines().collect::<Vec<_>>();
+        let tail = lines[lines.len().saturating_sub(STDERR_TAIL_LINES)..].join("\n");
+
+        Err(SeeedError::RemoteCommandFailed {
+            exit_code: self.exit_code,
+            statement: statement.to_string(),
+            stderr: tail,
+        })
+    }
+}
+
+/// Quotes a value so that it is a single word for the remote shell
+pub fn shell_quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', "'\\''"))
+}
+
+/// The sudo command running a shell command as root, or as another user, the password
+/// being read on its standard input when there is one
+///
+/// sudo does not read its input when no password is needed (NOPASSWD rule, or already root) :
+/// the command then runs with its input closed, so that an unread password never reaches it.
+pub fn sudo_command(become_user: Option<&String>, with_password: bool, command: &str) -> String {
+    let mut sudo = String::from("sudo ");
+    if with_password {
+        // -k : ignore the cached credentials, so that sudo asks for the password it is given
+        sudo.push_str("-S -k -p '' ");
+    }
+    if let Some(become_user) = become_user {
+        sudo.push_str(format!("-u {} ", shell_quote(become_user)).as_str());
+    }
+
+    match with_password {
+        true => format!("{}sh -c {}", sudo, shell_quote(&format!("exec < /dev/null; {}", command))),
+        false => format!("{}{}", sudo, command),
+    }
+}
+
+/// Waits for the remote end to close the channel and builds the command output
+fn wait_output(channel: &mut Channel, stdout: Vec<u8>, stderr: Vec<u8>) -> Result<CommandOutput, SeeedError> {
+    channel.wait_close()?;
+
+    Ok(CommandOutput {
+        exit_code: channel.exit_status()?,
+        stdout: String::from_utf8_lossy(&stdout).to_string(),
+        stderr: String::from_utf8_lossy(&stderr).to_string(),
+    })
+}
+
+/// Settings of a single `RemoteExecutor::run` call, overriding the defaults of the executor
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RunOptions {
+    /// the program running the script, such as `/bin/sh` or `python3`
+    pub shell: Option<String>,
+    /// the remote user running the script or owning the uploaded file, through sudo
+    pub become_user: Option<String>,
+    /// run as root through sudo, or not, instead of the executor default
+    pub sudo: Option<bool>,
+    /// maximum duration of the run, instead of the executor default
+    pub timeout: Option<Duration>,
+}
+
 pub trait RemoteExecutor {
-    fn connect(&mut self, target: &str) -> Result<(), SeeedError>;
-    fn command(&self, command: &str) -> Result<(), SeeedError>;
-    fn run(&self, script: &str) -> Result<(), SeeedError>;
-    fn upload(&self, content: &[u8], dst_path: String) -> Result<(), SeeedError>;
+    fn connect(&mut self, target: &Target) -> Result<(), SeeedError>;
+    fn command(&self, command: &str) -> Result<CommandOutput, SeeedError>;
+    fn run(&self, script: &str, options: &RunOptions) -> Result<CommandOutput, SeeedError>;
+    fn upload(&self, content: &[u8], dst_path: String, options: &RunOptions) -> Result<(), SeeedError>;
+
+    /// whether the connection is still usable, the script reconnecting between statements otherwise
+    fn is_alive(&self) -> bool {
+        true
+    }
 }
 
 pub struct SshClient {
     session: Option<Session>,
     use_sudo: bool,
+    shell: String,
+    options: SshOptions,
+    /// time of the last successful exchange with the server
+    last_exchange: Cell<Instant>,
 }
 
 impl RemoteExecutor for SshClient {
-    fn connect(&mut self, target: &str) -> Result<(), SeeedError> {
-        self.connect_impl(target)
+    fn connect(&mut self, target: &Target) -> Result<(), SeeedError> {
+        self.connect_impl(target).inspect(|_| self.last_exchange.set(Instant::now()))
     }
 
-    fn command(&self, command: &str) -> Result<(), SeeedError> {
-        self.command_impl(command)
+    fn command(&self, command: &str) -> Result<CommandOutput, SeeedError> {
+        self.command_impl(command).inspect(|_| self.last_exchange.set(Instant::now()))
     }
 
-    fn run(&self, script: &str) -> Result<(), SeeedError> {
-        self.run_impl(script)
+    fn run(&self, script: &str, options: &RunOptions) -> Result<CommandOutput, SeeedError> {
+        self.run_impl(script, options).inspect(|_| self.last_exchange.set(Instant::now()))
     }
 
-    fn upload(&self, content: &[u8], dst_path: String) -> Result<(), SeeedError> {
-        self.upload_impl(content, dst_path)
+    fn upload(&self, content: &[u8], dst_path: String, options: &RunOptions) -> Result<(), SeeedError> {
+        self.upload_impl(content, dst_path, options).inspect(|_| self.last_exchange.set(Instant::now()))
+    }
+
+    fn is_alive(&self) -> bool {
+        let Some(session) = &self.session else {
+            return false;
+        };
+
+        // a connection used within the keepalive interval is trusted, unless the keepalive fails ;
+        // a keepalive is not sent before its interval elapsed, so an idle connection is probed
+        let interval = match self.options.keepalive_interval.is_zero() {
+            true => DEFAULT_KEEPALIVE_INTERVAL,
+            false => self.options.keepalive_interval,
+        };
+        if session.keepalive_send().is_ok() && self.last_exchange.get().elapsed() < interval {
+            return true;
+        }
+
+        // opening a channel is a real round trip, bounded by the connect timeout so that a dead
+        // peer does not block the script
+        session.set_timeout(timeout_millis(self.options.connect_timeout));
+        let alive = session.channel_session().and_then(|mut channel| channel.close()).is_ok();
+        session.set_timeout(0);
+
+        if alive {
+            self.last_exchange.set(Instant::now());
+        }
+        alive
     }
 }
 
 impl SshClient {
 
-    pub fn new(use_sudo: bool) -> Self {
+    pub fn new(use_sudo: bool, shell: String, options: SshOptions) -> Self {
         Self {
             session: None,
             use_sudo,
+            shell,
+            options,
+            last_exchange: Cell::new(Instant::now()),
         }
     }
 
-    fn connect_impl(&mut self, target: &str) -> Result<(), SeeedError> {
+    fn connect_impl(&mut self, target: &Target) -> Result<(), SeeedError> {
 
-        // parse target
-        let pattern = regex::Regex::new(r"^(?P<username>[^:@]+)@(?P<hostname>[^:]+)(:(?P<port>\d+))?$")?;
-        let captures = pattern.captures(target).ok_or(SeeedError::BadTarget)?;
+        // the host may be an alias defined in the ssh config
+        let ssh_config_file = match &self.options.ssh_config_file {
+            Some(ssh_config_file) => ssh_config_file.clone(),
+            None => expand_home(DEFAULT_SSH_CONFIG_FILE),
+        };
+        let ssh_config = SshConfig::load(&ssh_config_file)?;
 
-        let host = captures.name("hostname").ok_or(SeeedError::BadTarget)?.as_str();
-        let username = captures.name("username").ok_or(SeeedError::BadTarget)?.as_str();
+        let endpoint = self.resolve(target, &ssh_config)?;
 
-        let port = match captures.name("port") {
-            Some(port) => port.as_str().parse::<u16>().map_err(|_| SeeedError::BadTarget)?,
-            None => 22,
+        // jump hosts given on the command line replace the ProxyJump of the ssh config
+        let jump_hosts = match (&self.options.jump_hosts, &endpoint.proxy_jump) {
+            (jump_hosts, _) if !jump_hosts.is_empty() => jump_hosts.clone(),
+            (_, Some(proxy_jump)) if proxy_jump != "none" => proxy_jump.split(',').map(Target::from_str).collect::<Result<_, _>>()?,
+            _ => Vec::new(),
         };
 
-        let (username, host, port) = (username, host, port);
+        // each session of the chain is opened through the previous one
+        let mut jump_session = None;
+        for jump_host in &jump_hosts {
+            let jump = self.resolve(jump_host, &ssh_config)?;
+            console::log(format!("connecting through {}", jump_host).as_str());
+            jump_session = Some(self.open_session(&jump, jump_session)?);
+        }
+
+        self.session = Some(self.open_session(&endpoint, jump_session)?);
 
-        // register the target
-        let target = format!("{}:{}",  host, port);
+        Ok(())
+    }
+
+    /// Resolves a target, its host possibly being an ssh config alias
+    fn resolve(&self, target: &Target, ssh_config: &SshConfig) -> Result<Endpoint, SeeedError> {
+        let host_config = ssh_config.lookup(&target.host)?;
+
+        let host = host_config.hostname.unwrap_or(target.host.clone());
+        let username = target.user.clone()
+            .or(host_config.user)
+            .or(std::env::var("USER").ok())
+            .ok_or(SeeedError::InvalidTarget(format!("{}: no user given, and no user in the ssh config", target)))?;
+        let port = target.port.or(host_config.port).unwrap_or(22);
+
+        let identity_files = match &self.options.identity_file {
+            Some(identity_file) => vec![identity_file.clone()],
+            None if !host_config.identity_files.is_empty() => host_config.identity_files,
+            None => DEFAULT_IDENTITY_FILES.iter().map(|path| expand_home(path)).filter(|path| path.exists()).collect(),
+        };
+
+        Ok(Endpoint {
+            host,
+            port,
+            username,
+            identity_files,
+            proxy_jump: host_config.proxy_jump,
+        })
+    }
+
+    /// Opens an authenticated session, directly or through a jump host session
+    fn open_session(&mut self, endpoint: &Endpoint, jump_session: Option<Session>) -> Result<Session, SeeedError> {
+        let (username, host, port) = (endpoint.username.as_str(), endpoint.host.as_str(), endpoint.port);
 
         // issue the connect process
-        let tcp = TcpStream::connect(target)?;
+        let tcp = match jump_session {
+            Some(jump_session) => tunnel(jump_session, host, port)?,
+            None => connect_tcp(host, port, self.options.connect_timeout)?,
+        };
         let mut session = Session::new()?;
         session.set_tcp_stream(tcp);
-        session.handshake()?;
+        session.set_timeout(timeout_millis(self.options.connect_timeout));
+        session.handshake().map_err(|e| match e.code() {
+            ssh2::ErrorCode::Session(LIBSSH2_ERROR_TIMEOUT) => SeeedError::ConnectTimeout(format!("{}:{}", host, port), self.options.connect_timeout),
+            _ => SeeedError::SshError(e),
+        })?;
+
+        // make sure we are talking to the expected server before sending any credential
+        self.verify_host_key(&session, host, port)?;
+
+        // try the authentication methods in the configured order
+        let mut authenticated = false;
+        for method in self.options.auth_methods.clone() {
+            let result = match method {
+                AuthMethod::Agent => Self::authenticate_with_agent(&session, username),
+                AuthMethod::PublicKey => Self::authenticate_with_key(&session, username, &endpoint.identity_files, &self.options.prompted),
+                AuthMethod::Password => self.authenticate_with_password(username, host, |password| {
+                    Ok(session.userauth_password(username, password.expose())?)
+                }),
+                AuthMethod::KeyboardInteractive => self.authenticate_with_password(username, host, |password| {
+                    Ok(session.userauth_keyboard_interactive(username, &mut PasswordPrompt { password })?)
+                }),
+            };
+
+            match result {
+                Ok(()) => {
+                    authenticated = true;
+                    break
+                },
+                Err(e) => log::debug!("{} authentication failed: {}", method, e),
+            }
+        }
+
+        if !authenticated {
+            let methods = self.options.auth_methods.iter().map(|method| method.to_string()).collect::<Vec<_>>();
+            return Err(SeeedError::AuthenticationFailed(username.to_string(), methods.join(", ")))
+        }
+
+        // long commands are bounded by the command timeout, not by the session one
+        session.set_timeout(0);
+        if !self.options.keepalive_interval.is_zero() {
+            session.set_keepalive(false, self.options.keepalive_interval.as_secs().max(1) as u32);
+        }
+
+        Ok(session)
+    }
 
-        // try to authenticate using the ssh agent
+    fn verify_host_key(&self, session: &Session, host: &str, port: u16) -> Result<(), SeeedError> {
+        if self.options.host_key_check == HostKeyCheck::Off {
+            return Ok(());
+        }
+
+        let (key, key_type) = session.host_key().ok_or(SeeedError::GenericSshError("no host key sent by the server".to_string()))?;
+        let fingerprint = fingerprint(session.host_key_hash(HashType::Sha256).unwrap_or_default());
+
+        let known_hosts_file = match &self.options.known_hosts_file {
+            Some(known_hosts_file) => known_hosts_file.clone(),
+            None => expand_home(DEFAULT_KNOWN_HOSTS_FILE),
+        };
+
+        let mut known_hosts = session.known_hosts()?;
+        if known_hosts_file.exists() {
+            known_hosts.read_file(&known_hosts_file, KnownHostFileKind::OpenSSH)?;
+        }
+
+        // non standard ports are recorded as [host]:port
+        let name = match port {
+            22 => host.to_string(),
+            _ => format!("[{}]:{}", host, port),
+        };
+
+        match known_hosts.check_port(host, port, key) {
+            CheckResult::Match => Ok(()),
+            CheckResult::Mismatch => Err(SeeedError::HostKeyMismatch {
+                host: name,
+                fingerprint,
+                known_hosts: known_hosts_file.display().to_string(),
+            }),
+            CheckResult::NotFound if self.options.host_key_check == HostKeyCheck::Strict => {
+                Err(SeeedError::UnknownHostKey { host: name, fingerprint })
+            }
+            CheckResult::NotFound => {
+                // append the new host to the known hosts file, leaving the existing entries untouched
+                known_hosts.add(&name, key, "", key_type.into())?;
+                let entry = known_hosts.hosts()?.pop().ok_or(SeeedError::GenericSshError("cannot record the host key".to_string()))?;
+                let line = known_hosts.write_string(&entry, KnownHostFileKind::OpenSSH)?;
+
+                if let Some(directory) = known_hosts_file.parent() {
+                    std::fs::create_dir_all(directory)?;
+                }
+                let mut file = std::fs::OpenOptions::new().create(true).append(true).open(&known_hosts_file)?;
+                file.write_all(line.as_bytes())?;
+
+                console::log(format!("added {} ({}) to {}", name, fingerprint, known_hosts_file.display()).as_str());
+                Ok(())
+            }
+            CheckResult::Failure => Err(SeeedError::GenericSshError(format!("cannot check the host key of {}", name))),
+        }
+    }
+
+    fn authenticate_with_agent(session: &Session, username: &str) -> Result<(), SeeedError> {
         let mut agent = session.agent()?;
         agent.connect()?;
         agent.list_identities()?;
-        let identities = agent.identities()?;
 
-        let mut authenticated = false;
+        for identity in agent.identities()?.iter() {
+            if agent.userauth(username, identity).is_ok() {
+                return Ok(());
+            }
+        }
 
-        for identity in identities.iter() {
-            match agent.userauth(username, identity) {
-                Ok(_) => {
-                    authenticated = true;
-                    break
+        Err(SeeedError::GenericSshError("no agent identity was accepted".to_string()))
+    }
 
-                },
-                Err(_) => continue,
+    fn authenticate_with_key(session: &Session, username: &str, identity_files: &[PathBuf], prompted: &Mutex<Prompted>) -> Result<(), SeeedError> {
+        for identity_file in identity_files {
+            let passphrase = std::env::var(PASSPHRASE_ENV_VAR).ok();
+
+            let result = match session.userauth_pubkey_file(username, None, identity_file, passphrase.as_deref()) {
+                // the key is encrypted : ask for its passphrase when running interactively, once per run
+                Err(e) if e.code() == ssh2::ErrorCode::Session(LIBSSH2_ERROR_FILE) && passphrase.is_none() && std::io::stdin().is_terminal() => {
+                    let mut prompted = prompted.lock().unwrap();
+                    let passphrase = match prompted.passphrases.get(identity_file) {
+                        Some(passphrase) => passphrase.clone(),
+                        None => Secret::new(rpassword::prompt_password(format!("passphrase for {}: ", identity_file.display()))?),
+                    };
+                    let result = session.userauth_pubkey_file(username, None, identity_file, Some(passphrase.expose()));
+                    if result.is_ok() {
+                        prompted.passphrases.insert(identity_file.clone(), passphrase);
+                    }
+                    result
+                }
+                result => result,
+            };
+
+            match result {
+                Ok(()) => return Ok(()),
+                Err(e) => log::debug!("key {} was not accepted: {}", identity_file.display(), e),
             }
         }
 
-        if authenticated == false {
-            return Err(SeeedError::BadTarget)
+        Err(SeeedError::GenericSshError("no private key file was accepted".to_string()))
+    }
+
+    /// authenticates with the password of the remote user, prompted for when running interactively
+    ///
+    /// a prompted password is kept once accepted, for the reconnections to the same host
+    fn authenticate_with_password(&self, username: &str, host: &str, authenticate: impl Fn(&Secret) -> Result<(), SeeedError>) -> Result<(), SeeedError> {
+        if let Some(password) = &self.options.password {
+            return authenticate(password);
+        }
+
+        // the other hosts wait for the prompt to be answered and checked
+        let key = format!("{}@{}", username, host);
+        let mut prompted = self.options.prompted.lock().unwrap();
+        if let Some(password) = prompted.passwords.get(&key) {
+            return authenticate(password);
+        }
+
+        if !std::io::stdin().is_terminal() {
+            return Err(SeeedError::GenericSshError(format!("no password available, set {}", PASSWORD_ENV_VAR)));
         }
 
-        self.session = Some(session);
+        let password = Secret::new(rpassword::prompt_password(format!("password for {}: ", key))?);
+        authenticate(&password)?;
+        prompted.passwords.insert(key, password);
 
         Ok(())
     }
 
-    fn command_impl(&self, command: &str) -> Result<(), SeeedError> {
+    fn command_impl(&self, command: &str) -> Result<CommandOutput, SeeedError> {
         let session = self.session.as_ref().ok_or(SeeedError::GenericSshError("Session not initialized".to_string()))?.clone();
         let mut channel = session.channel_session()?;
         channel.exec(command)?;
 
         // read the output
-        let mut stdout = String::new();
-        channel.read_to_string(&mut stdout)?;
+        let mut stdout = Vec::new();
+        channel.read_to_end(&mut stdout)?;
+        let mut stderr = Vec::new();
+        channel.stderr().read_to_end(&mut stderr)?;
 
-        channel.wait_close()?;
-        Ok(())
+        wait_output(&mut channel, stdout, stderr)
     }
 
-    fn run_impl(&self, script: &str) -> Result<(), SeeedError> {
+    fn run_impl(&self, script: &str, options: &RunOptions) -> Result<CommandOutput, SeeedError> {
 
         let session = self.session.as_ref().ok_or(SeeedError::GenericSshError("Session not initialized".to_string()))?.clone();
 
@@ -154,12 +796,17 @@ impl SshClient {
         let _remote_file = RemoteTempFile::new(sftp.clone(), remote_script_path.clone());
 
         // execute the script
+        let shell = options.shell.as_ref().unwrap_or(&self.shell);
+        let pid_path = format!("{}/script_{}.pid", REMOTE_TEMP_DIR, uuid::Uuid::new_v4());
+        let command = format!(
+            "sh -c {} sh {} {} {}",
+            shell_quote(RUN_WRAPPER),
+            shell_quote(&pid_path),
+            shell_quote(shell),
+            shell_quote(&remote_script_path)
+        );
         let mut channel = session.channel_session()?;
-        if self.use_sudo {
-            channel.exec(format!("sudo /bin/bash {}", remote_script_path).as_str())?;
-        } else {
-            channel.exec(format!("/bin/bash {}", remote_script_path).as_str())?;
-        }
+        self.exec(&mut channel, &command, options)?;
 
 
         // pipe channel to a formater
@@ -168,8 +815,13 @@ impl SshClient {
         // Set non-blocking to true to enable polling
         session.set_blocking(false);
 
+        let timeout = options.timeout.or(self.options.command_timeout);
+        let started = Instant::now();
+
         let mut stdout_buf: Vec<u8> = Vec::new();
         let mut stderr_buf: Vec<u8> = Vec::new();
+        let mut stdout_all: Vec<u8> = Vec::new();
+        let mut stderr_all: Vec<u8> = Vec::new();
         let mut buff = [0u8; 1024];
 
         let mut stdout_done = false;
@@ -184,12 +836,12 @@ impl SshClient {
                     Ok(0) => { stdout_done = true; }
                     Ok(n) => {
                         made_progress = true;
+                        stdout_all.extend_from_slice(&buff[..n]);
                         stdout_buf.extend_from_slice(&buff[..n]);
                         while let Some(pos) = stdout_buf.iter().position(|&b| b == b'\n') {
                             let line_bytes = stdout_buf.drain(..=pos).collect::<Vec<u8>>();
                             let line = String::from_utf8_lossy(&line_bytes);
-                            // Print with newline as originally intended (line includes \n)
-                            print!("   | {}", line.yellow());
+                            console::output(&line, false);
                         }
                     }
                     Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => {}
@@ -203,11 +855,12 @@ impl SshClient {
                     Ok(0) => { stderr_done = true; }
                     Ok(n) => {
                         made_progress = true;
+                        stderr_all.extend_from_slice(&buff[..n]);
                         stderr_buf.extend_from_slice(&buff[..n]);
                         while let Some(pos) = stderr_buf.iter().position(|&b| b == b'\n') {
                             let line_bytes = stderr_buf.drain(..=pos).collect::<Vec<u8>>();
                             let line = String::from_utf8_lossy(&line_bytes);
-                            print!("   | {}", line.red());
+                            console::output(&line, true);
                         }
                     }
                     Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => {}
@@ -219,41 +872,45 @@ impl SshClient {
                 break;
             }
 
+            // give up on the command : it is killed, then closing the channel stops waiting for it
+            if let Some(timeout) = timeout.filter(|timeout| started.elapsed() > *timeout) {
+                session.set_blocking(true);
+                if let Err(e) = self.kill(&session, &pid_path, options) {
+                    log::debug!("cannot stop the timed out script: {}", e);
+                }
+                let _ = channel.close();
+                return Err(SeeedError::CommandTimeout(timeout));
+            }
+
             if !made_progress {
+                // keep the connection alive during long silent commands
+                let _ = session.keepalive_send();
                 thread::sleep(std::time::Duration::from_millis(10));
             }
         }
 
         // Print any remaining content in buffers
         if !stdout_buf.is_empty() {
-            let line = String::from_utf8_lossy(&stdout_buf);
-            print!("   | {}", line.yellow());
-            if !line.ends_with('\n') { println!(); }
+            console::output(&String::from_utf8_lossy(&stdout_buf), false);
         }
         if !stderr_buf.is_empty() {
-             let line = String::from_utf8_lossy(&stderr_buf);
-             print!("   | {}", line.red());
-             if !line.ends_with('\n') { println!(); }
+            console::output(&String::from_utf8_lossy(&stderr_buf), true);
         }
 
         session.set_blocking(true);
 
-        // wait for the script to finish
-        // @todo
-
-        // remove the script from the remote target
-        // Handled by RemoteTempFile Drop
-
-        Ok(())
+        // wait for the script to finish and collect its exit status
+        // (the script is removed from the remote target by the RemoteTempFile Drop)
+        wait_output(&mut channel, stdout_all, stderr_all)
     }
 
-    fn upload_impl(&self, content: &[u8], dst_path: String) -> Result<(), SeeedError> {
+    fn upload_impl(&self, content: &[u8], dst_path: String, options: &RunOptions) -> Result<(), SeeedError> {
         
         let session = self.session.as_ref().ok_or(SeeedError::GenericSshError("Session not initialized".to_string()))?.clone();
         let sftp = Arc::new(session.sftp()?);
 
         // If using sudo, we upload to a temp file first, then move it
-        if self.use_sudo {
+        if options.sudo.unwrap_or(self.use_sudo) || self.become_user(options).is_some() {
             let temp_path = format!("{}/upload_{}.data", REMOTE_TEMP_DIR, uuid::Uuid::new_v4());
             let path = Path::new(&temp_path);
             
@@ -266,8 +923,18 @@ impl SshClient {
             // the unlink in Drop will just fail silently (or we can let it fail).
             let _remote_temp_file = RemoteTempFile::new(sftp.clone(), temp_path.clone());
 
+            // another user cannot move a file out of the sticky /tmp : it gets a copy
+            let command = match self.become_user(options) {
+                Some(_) => format!("cp {} {}", shell_quote(&temp_path), shell_quote(&dst_path)),
+                None => format!("mv {} {}", shell_quote(&temp_path), shell_quote(&dst_path)),
+            };
             let mut channel = session.channel_session()?;
-            channel.exec(format!("sudo mv {} {}", temp_path, dst_path).as_str())?;
+            self.exec(&mut channel, &command, options)?;
+
+            let mut stderr = Vec::new();
+            channel.stderr().read_to_end(&mut stderr)?;
+
+            wait_output(&mut channel, Vec::new(), stderr)?.check(&command)?;
         } else {
             // Direct upload
             let path = Path::new(dst_path.as_str());
@@ -279,4 +946,41 @@ impl SshClient {
         Ok(())
     }
 
+    /// terminates a script started by RUN_WRAPPER, as the user running it
+    fn kill(&self, session: &Session, pid_path: &str, options: &RunOptions) -> Result<(), SeeedError> {
+        let command = format!("sh -c {} sh {}", shell_quote(KILL_SCRIPT), shell_quote(pid_path));
+        let mut channel = session.channel_session()?;
+        self.exec(&mut channel, &command, options)?;
+
+        let mut stderr = Vec::new();
+        channel.stderr().read_to_end(&mut stderr)?;
+        wait_output(&mut channel, Vec::new(), stderr)?.check(&command)
+    }
+
+    fn become_user<'a>(&'a self, options: &'a RunOptions) -> Option<&'a String> {
+        options.become_user.as_ref().or(self.options.become_user.as_ref())
+    }
+
+    /// starts a command on the channel, through sudo when running as root or as another user
+    ///
+    /// the sudo password is written on the standard input of sudo (`-S`), so that it never
+    /// appears in the command line nor in the output, and the command never reads it
+    fn exec(&self, channel: &mut Channel, command: &str, options: &RunOptions) -> Result<(), SeeedError> {
+        let become_user = self.become_user(options);
+        if !options.sudo.unwrap_or(self.use_sudo) && become_user.is_none() {
+            channel.exec(command)?;
+            return Ok(());
+        }
+
+        channel.exec(&sudo_command(become_user, self.options.sudo_password.is_some(), command))?;
+
+        if let Some(password) = &self.options.sudo_password {
+            channel.write_all(format!("{}\n", password.expose()).as_bytes())?;
+            channel.flush()?;
+            channel.send_eof()?;
+        }
+
+        Ok(())
+    }
+
 }
\ No newline at end of file
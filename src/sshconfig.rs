@@ -0,0 +1,208 @@
+use std::path::{Path, PathBuf};
+
+use crate::error::SeeedError;
+use crate::sshclient::expand_home;
+
+/// maximum depth of nested `Include` directives
+const MAX_INCLUDE_DEPTH: usize = 16;
+
+/// The settings of a host, resolved from the OpenSSH client configuration
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct HostConfig {
+    pub hostname: Option<String>,
+    pub user: Option<String>,
+    pub port: Option<u16>,
+    pub identity_files: Vec<PathBuf>,
+    pub proxy_jump: Option<String>,
+}
+
+/// A `Host` section : its patterns and its `(keyword, value)` options
+#[derive(Debug)]
+struct Section {
+    patterns: Vec<String>,
+    options: Vec<(String, String)>,
+}
+
+/// The OpenSSH client configuration (`~/.ssh/config`)
+///
+/// Only the options used by seeed are interpreted : `HostName`, `User`, `Port`, `IdentityFile` and `ProxyJump`.
+/// As with OpenSSH, the first value found for an option wins, so specific `Host` sections must
+/// come before the generic ones such as `Host *`.
+#[derive(Debug, Default)]
+pub struct SshConfig {
+    sections: Vec<Section>,
+}
+
+/// Matches a host name against a pattern where `*` matches any sequence of characters and `?` any character
+pub fn wildcard_match(pattern: &str, name: &str) -> bool {
+    let pattern = pattern.chars().collect::<Vec<_>>();
+    let name = name.chars().collect::<Vec<_>>();
+
+    // iterative matching, backtracking to the last `*`
+    let (mut p, mut n) = (0, 0);
+    let mut star: Option<(usize, usize)> = None;
+
+    while n < name.len() {
+        if p < pattern.len() && (pattern[p] == '?' || pattern[p] == name[n]) {
+            p += 1;
+            n += 1;
+        } else if p < pattern.len() && pattern[p] == '*' {
+            star = Some((p, n));
+            p += 1;
+        } else if let Some((star_p, star_n)) = star {
+            p = star_p + 1;
+            n = star_n + 1;
+            star = Some((star_p, star_n + 1));
+        } else {
+            return false;
+        }
+    }
+
+    pattern[p..].iter().all(|c| *c == '*')
+}
+
+/// Splits a configuration line into its keyword and its value (`Keyword value` or `Keyword=value`)
+fn split_line(line: &str) -> Option<(String, String)> {
+    let line = line.trim();
+    if line.is_empty() || line.starts_with('#') {
+        return None;
+    }
+
+    let end = line.find(|c: char| c.is_whitespace() || c == '=').unwrap_or(line.len());
+    let keyword = line[..end].to_lowercase();
+    let rest = line[end..].trim_start();
+    let value = rest.strip_prefix('=').unwrap_or(rest).trim();
+
+    Some((keyword, value.trim_matches('"').to_string()))
+}
+
+impl SshConfig {
+
+    /// Loads a configuration file, an absent file giving an empty configuration
+    pub fn load(path: &Path) -> Result<Self, SeeedError> {
+        let mut config = SshConfig::default();
+        if path.exists() {
+            config.read_file(path, 0)?;
+        }
+        Ok(config)
+    }
+
+    /// Parses the content of a configuration file, relative includes being resolved in `~/.ssh`
+    pub fn parse(contents: &str) -> Result<Self, SeeedError> {
+        let mut config = SshConfig::default();
+        config.read_lines(contents, 0)?;
+        Ok(config)
+    }
+
+    fn read_file(&mut self, path: &Path, depth: usize) -> Result<(), SeeedError> {
+        let contents = std::fs::read_to_string(path)
+            .map_err(|e| SeeedError::SshConfig(format!("cannot read {}: {}", path.display(), e)))?;
+        self.read_lines(&contents, depth)
+    }
+
+    fn read_lines(&mut self, contents: &str, depth: usize) -> Result<(), SeeedError> {
+        for (keyword, value) in contents.lines().filter_map(split_line) {
+            match keyword.as_str() {
+                "host" => self.sections.push(Section {
+                    patterns: value.split_whitespace().map(str::to_string).collect(),
+                    options: Vec::new(),
+                }),
+                // `Match` conditions are not supported : the section never applies
+                "match" => self.sections.push(Section {
+                    patterns: Vec::new(),
+                    options: Vec::new(),
+                }),
+                "include" => {
+                    if depth >= MAX_INCLUDE_DEPTH {
+                        return Err(SeeedError::SshConfig(format!("too many nested includes ({})", value)));
+                    }
+                    for pattern in value.split_whitespace() {
+                        for path in Self::include_paths(pattern)? {
+                            self.read_file(&path, depth + 1)?;
+                        }
+                    }
+                }
+                _ => {
+                    // options before the first `Host` line apply to every host
+                    if self.sections.is_empty() {
+                        self.sections.push(Section {
+                            patterns: vec!["*".to_string()],
+                            options: Vec::new(),
+                        });
+                    }
+                    self.sections.last_mut().unwrap().options.push((keyword, value));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// The files matching an `Include` pattern, sorted by name
+    fn include_paths(pattern: &str) -> Result<Vec<PathBuf>, SeeedError> {
+        let path = expand_home(pattern);
+        let path = match path.is_absolute() {
+            true => path,
+            false => expand_home("~/.ssh").join(path),
+        };
+
+        let file_name = path.file_name().map(|name| name.to_string_lossy().to_string()).unwrap_or_default();
+        if !file_name.contains(['*', '?']) {
+            return Ok(if path.exists() { vec![path] } else { Vec::new() });
+        }
+
+        let directory = path.parent().unwrap_or(Path::new("/"));
+        let entries = match std::fs::read_dir(directory) {
+            Ok(entries) => entries,
+            Err(_) => return Ok(Vec::new()),
+        };
+
+        let mut paths = entries
+            .filter_map(|entry| entry.ok())
+            .filter(|entry| wildcard_match(&file_name, &entry.file_name().to_string_lossy()))
+            .map(|entry| entry.path())
+            .filter(|path| path.is_file())
+            .collect::<Vec<_>>();
+        paths.sort();
+
+        Ok(paths)
+    }
+
+    /// Whether a host matches the patterns of a `Host` line, negated patterns excluding it
+    fn matches(patterns: &[String], host: &str) -> bool {
+        let mut matched = false;
+        for pattern in patterns {
+            match pattern.strip_prefix('!') {
+                Some(negated) if wildcard_match(negated, host) => return false,
+                Some(_) => {}
+                None => matched |= wildcard_match(pattern, host),
+            }
+        }
+        matched
+    }
+
+    /// Resolves the settings of a host, as given on the command line
+    pub fn lookup(&self, host: &str) -> Result<HostConfig, SeeedError> {
+        let mut config = HostConfig::default();
+
+        let sections = self.sections.iter().filter(|section| Self::matches(&section.patterns, host));
+        for (keyword, value) in sections.flat_map(|section| section.options.iter()) {
+            match keyword.as_str() {
+                "hostname" if config.hostname.is_none() => {
+                    config.hostname = Some(value.replace("%h", host).replace("%%", "%"));
+                }
+                "user" if config.user.is_none() => config.user = Some(value.clone()),
+                "port" if config.port.is_none() => {
+                    let port = value.parse::<u16>().map_err(|_| SeeedError::SshConfig(format!("invalid port {} for host {}", value, host)))?;
+                    config.port = Some(port);
+                }
+                "proxyjump" if config.proxy_jump.is_none() => config.proxy_jump = Some(value.clone()),
+                "identityfile" => {
+                    config.identity_files.push(expand_home(&value.replace("%h", host).replace("%%", "%")));
+                }
+                _ => {}
+            }
+        }
+
+        Ok(config)
+    }
+}
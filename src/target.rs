@@ -0,0 +1,143 @@
+use std::fmt;
+use std::str::FromStr;
+
+use crate::container::ContainerRuntime;
+use crate::error::SeeedError;
+
+/// A remote host to run a script on
+///
+/// Targets are written `[user@]host[:port]` or `ssh://[user@]host[:port]`, IPv6 addresses being
+/// enclosed in brackets when a port is given (`root@[2001:db8::1]:2222`). The user and the port are
+/// optional : they are then taken from the ssh config, or default to the local user and port 22.
+/// The `local` target runs the scripts on the local machine, and `docker://[user@]container` or
+/// `podman://[user@]container` targets in a running container, `host` being the container name.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Target {
+    pub user: Option<String>,
+    pub host: String,
+    pub port: Option<u16>,
+    /// the program running the container, for container targets
+    pub runtime: Option<ContainerRuntime>,
+}
+
+/// host name of the target running the scripts on the local machine, without ssh
+pub const LOCAL_HOST: &str = "local";
+
+fn invalid(target: &str, reason: &str) -> SeeedError {
+    SeeedError::InvalidTarget(format!("{}: {}", target, reason))
+}
+
+fn parse_port(target: &str, port: &str) -> Result<u16, SeeedError> {
+    match port.parse::<u16>() {
+        Ok(port) if port > 0 => Ok(port),
+        _ => Err(invalid(target, &format!("invalid port {}", port))),
+    }
+}
+
+/// Parses a `docker://[user@]container` or `podman://[user@]container` target
+fn parse_container(target: &str, runtime: ContainerRuntime, address: &str) -> Result<Target, SeeedError> {
+    let (user, container) = match address.trim_end_matches('/').rsplit_once('@') {
+        Some(("", _)) => return Err(invalid(target, "empty user name")),
+        Some((user, container)) => (Some(user.to_string()), container),
+        None => (None, address.trim_end_matches('/')),
+    };
+
+    // the names allowed by docker and podman
+    let valid = container.starts_with(|c: char| c.is_ascii_alphanumeric())
+        && container.chars().all(|c| c.is_ascii_alphanumeric() || "_.-".contains(c));
+    if !valid {
+        return Err(invalid(target, &format!("invalid container name {}", container)));
+    }
+
+    Ok(Target {
+        user,
+        host: container.to_string(),
+        port: None,
+        runtime: Some(runtime),
+    })
+}
+
+impl FromStr for Target {
+    type Err = SeeedError;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let target = value.trim();
+
+        for runtime in ContainerRuntime::ALL {
+            if let Some(address) = target.strip_prefix(runtime.program()).and_then(|rest| rest.strip_prefix("://")) {
+                return parse_container(target, runtime, address);
+            }
+        }
+
+        // ssh://[user@]host[:port], a trailing slash being allowed
+        let address = match target.strip_prefix("ssh://") {
+            Some(rest) if rest.trim_end_matches('/').contains('/') => return Err(invalid(target, "ssh URLs cannot have a path")),
+            Some(rest) => rest.trim_end_matches('/'),
+            None => target,
+        };
+
+        let (user, address) = match address.rsplit_once('@') {
+            Some(("", _)) => return Err(invalid(target, "empty user name")),
+            Some((user, address)) => (Some(user.to_string()), address),
+            None => (None, address),
+        };
+
+        let (host, port) = if let Some(rest) = address.strip_prefix('[') {
+            // [ipv6]:port
+            let (host, rest) = rest.split_once(']').ok_or(invalid(target, "missing closing bracket"))?;
+            match rest {
+                "" => (host, None),
+                _ => match rest.strip_prefix(':') {
+                    Some(port) => (host, Some(parse_port(target, port)?)),
+                    None => return Err(invalid(target, "unexpected characters after the closing bracket")),
+                },
+            }
+        } else if address.matches(':').count() > 1 {
+            // bare ipv6 address, which cannot have a port
+            (address, None)
+        } else {
+            match address.split_once(':') {
+                Some((host, port)) => (host, Some(parse_port(target, port)?)),
+                None => (address, None),
+            }
+        };
+
+        if host.is_empty() {
+            return Err(invalid(target, "empty host name"));
+        }
+        if host.contains(|c: char| c.is_whitespace() || "/@[]".contains(c)) {
+            return Err(invalid(target, &format!("invalid host name {}", host)));
+        }
+
+        Ok(Target {
+            user,
+            host: host.to_string(),
+            port,
+            runtime: None,
+        })
+    }
+}
+
+impl Target {
+
+    /// whether this is the `local` target, run without ssh
+    pub fn is_local(&self) -> bool {
+        self.host == LOCAL_HOST && self.user.is_none() && self.port.is_none() && self.runtime.is_none()
+    }
+}
+
+impl fmt::Display for Target {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(runtime) = &self.runtime {
+            write!(f, "{}://", runtime)?;
+        }
+        if let Some(user) = &self.user {
+            write!(f, "{}@", user)?;
+        }
+        match (self.host.contains(':'), self.port) {
+            (true, Some(port)) => write!(f, "[{}]:{}", self.host, port),
+            (false, Some(port)) => write!(f, "{}:{}", self.host, port),
+            (_, None) => write!(f, "{}", self.host),
+        }
+    }
+}
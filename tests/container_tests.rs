@@ -0,0 +1,118 @@
+use std::os::unix::fs::PermissionsExt;
+use std::path::PathBuf;
+use std::sync::Once;
+
+use seeed::container::ContainerExecutor;
+use seeed::error::SeeedError;
+use seeed::script::ScriptContext;
+use seeed::sshclient::{RemoteExecutor, RunOptions, SshOptions};
+
+/// a fake `docker` program : the `web` container is running, and `exec` runs the command
+/// locally, with the user it runs as in `EXEC_USER` ; the `exiting` container stops before `exec`
+const FAKE_DOCKER: &str = r#"#!/bin/sh
+case "$1" in
+    inspect) case "$4" in web|exiting) echo true ;; *) echo false ;; esac ;;
+    exec)
+        shift
+        [ "$1" = "-i" ] && shift
+        EXEC_USER=default
+        if [ "$1" = "-u" ]; then EXEC_USER=$2; shift 2; fi
+        export EXEC_USER
+        if [ "$1" = "exiting" ]; then echo "container exiting is not running" >&2; exit 1; fi
+        shift
+        exec "$@"
+        ;;
+esac
+"#;
+
+/// a directory of the build tree, kept out of the system temporary directory
+fn directory() -> PathBuf {
+    PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("container_tests")
+}
+
+/// an executor running the fake `docker` program
+fn executor() -> ContainerExecutor {
+    static INSTALL: Once = Once::new();
+    let program = directory().join("docker");
+
+    // installed once : running a file while another thread writes one can fail with ETXTBSY
+    INSTALL.call_once(|| {
+        std::fs::create_dir_all(directory()).unwrap();
+        std::fs::write(&program, FAKE_DOCKER).unwrap();
+        std::fs::set_permissions(&program, std::fs::Permissions::from_mode(0o755)).unwrap();
+    });
+
+    ContainerExecutor::new(false, "/bin/sh".to_string(), SshOptions::default(), Some(program))
+}
+
+fn connected(target: &str) -> Result<ContainerExecutor, SeeedError> {
+    let mut executor = executor();
+    executor.connect(&target.parse().unwrap())?;
+    Ok(executor)
+}
+
+#[test]
+fn test_container_not_running() {
+    assert!(matches!(connected("docker://db"), Err(SeeedError::ContainerError(_))));
+
+    assert!(matches!(executor().run("true", &RunOptions::default()), Err(SeeedError::ContainerError(_))));
+}
+
+#[test]
+fn test_container_run() {
+    let executor = connected("docker://web").unwrap();
+
+    let output = executor.run("echo hello\necho oops >&2\nexit 3\n", &RunOptions::default()).unwrap();
+    assert_eq!(output.exit_code, 3);
+    assert_eq!(output.stdout, "hello\n");
+    assert_eq!(output.stderr, "oops\n");
+
+    let options = RunOptions { shell: Some("cat".to_string()), ..RunOptions::default() };
+    assert_eq!(executor.run("not a script\n", &options).unwrap().stdout, "not a script\n");
+}
+
+#[test]
+fn test_container_exec_failure() {
+    // the input is larger than a pipe buffer, exec exits without reading it
+    let executor = connected("docker://exiting").unwrap();
+    let script = "true\n".repeat(100_000);
+
+    let output = executor.run(&script, &RunOptions::default()).unwrap();
+    assert_eq!(output.exit_code, 1);
+    assert_eq!(output.stderr, "container exiting is not running\n");
+
+    let result = executor.upload(script.as_bytes(), "/tmp/app.conf".to_string(), &RunOptions::default());
+    assert!(matches!(result, Err(SeeedError::RemoteCommandFailed { ref stderr, .. }) if stderr.contains("not running")), "{:?}", result);
+}
+
+#[test]
+fn test_container_users() {
+    let executor = connected("docker://app@web").unwrap();
+
+    let sudo = RunOptions { sudo: Some(true), ..RunOptions::default() };
+    let become_user = RunOptions { become_user: Some("postgres".to_string()), ..RunOptions::default() };
+    let users = [RunOptions::default(), sudo, become_user]
+        .iter()
+        .map(|options| executor.run("echo $EXEC_USER", options).unwrap().stdout)
+        .collect::<Vec<_>>();
+    assert_eq!(users, vec!["app\n", "root\n", "postgres\n"]);
+}
+
+#[test]
+fn test_container_script() {
+    let directory = directory().join("script");
+    std::fs::create_dir_all(&directory).unwrap();
+    let destination = directory.join("app.conf");
+
+    let script = format!(
+        "let config = <<<EOF\nport = 8080\nEOF>>>\nupload($config, \"{}\")\nlet port = | grep -c 8080 {}\n| test {{{{ port }}}} = 1\n",
+        destination.display(),
+        destination.display()
+    );
+    let executor = Box::new(executor());
+    let mut context = ScriptContext::new(Some("docker://web".parse().unwrap()), false, script, executor);
+    context.run(false).unwrap();
+
+    assert!(std::fs::read_to_string(&destination).unwrap().contains("port = 8080"));
+    std::fs::remove_dir_all(&directory).unwrap();
+}
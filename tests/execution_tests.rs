@@ -1,5 +1,6 @@
 use seeed::script::ScriptContext;
-use seeed::sshclient::RemoteExecutor;
+use seeed::target::Target;
+use seeed::sshclient::{CommandOutput, RemoteExecutor, RunOptions};
 use seeed::error::SeeedError;
 use std::sync::{Arc, Mutex};
 
@@ -7,35 +8,47 @@ use std::sync::{Arc, Mutex};
 #[derive(Clone)]
 struct MockExecutor {
     commands: Arc<Mutex<Vec<String>>>,
-    uploads: Arc<Mutex<Vec<(String, String)>>>
+    run_options: Arc<Mutex<Vec<RunOptions>>>,
+    uploads: Arc<Mutex<Vec<(String, String)>>>,
+    upload_options: Arc<Mutex<Vec<RunOptions>>>,
+    stdout: String,
 }
 
 impl MockExecutor {
     fn new() -> Self {
+        Self::with_stdout("")
+    }
+
+    fn with_stdout(stdout: &str) -> Self {
         Self {
             commands: Arc::new(Mutex::new(Vec::new())),
+            run_options: Arc::new(Mutex::new(Vec::new())),
             uploads: Arc::new(Mutex::new(Vec::new())),
+            upload_options: Arc::new(Mutex::new(Vec::new())),
+            stdout: stdout.to_string(),
         }
     }
 }
 
 impl RemoteExecutor for MockExecutor {
-    fn connect(&mut self, _target: &str) -> Result<(), SeeedError> {
+    fn connect(&mut self, _target: &Target) -> Result<(), SeeedError> {
         Ok(())
     }
 
-    fn command(&self, command: &str) -> Result<(), SeeedError> {
+    fn command(&self, command: &str) -> Result<CommandOutput, SeeedError> {
         self.commands.lock().unwrap().push(command.to_string());
-        Ok(())
+        Ok(CommandOutput::default())
     }
 
-    fn run(&self, script: &str) -> Result<(), SeeedError> {
+    fn run(&self, script: &str, options: &RunOptions) -> Result<CommandOutput, SeeedError> {
         self.commands.lock().unwrap().push(format!("RUN: {}", script));
-        Ok(())
+        self.run_options.lock().unwrap().push(options.clone());
+        Ok(CommandOutput { exit_code: 0, stdout: self.stdout.clone(), stderr: String::new() })
     }
 
-    fn upload(&self, content: &str, dst_path: String) -> Result<(), SeeedError> {
-        self.uploads.lock().unwrap().push((content.to_string(), dst_path));
+    fn upload(&self, content: &[u8], dst_path: String, options: &RunOptions) -> Result<(), SeeedError> {
+        self.uploads.lock().unwrap().push((String::from_utf8_lossy(content).to_string(), dst_path));
+        self.upload_options.lock().unwrap().push(options.clone());
         Ok(())
     }
 }
@@ -46,13 +59,13 @@ fn test_execution_simple_remote() {
 
     let mock = MockExecutor::new();
     let executor = Box::new(mock.clone());
-    let mut context = ScriptContext::new("user@host".to_string(), false, script_content.to_string(), executor);
+    let mut context = ScriptContext::new(Some("user@host".parse().unwrap()), false, script_content.to_string(), executor);
 
     context.run(false).unwrap();
 
     let commands = mock.commands.lock().unwrap();
     // 0: RUN:  echo "hello"
-    assert!(commands.len() >= 1);
+    assert!(!commands.is_empty());
     assert_eq!(commands[0], "RUN:  echo \"hello\"");
 }
 
@@ -62,7 +75,7 @@ fn test_loop_execution() {
 
     let mock = MockExecutor::new();
     let executor = Box::new(mock.clone());
-    let mut context = ScriptContext::new("user@host".to_string(), false, script_content.to_string(), executor);
+    let mut context = ScriptContext::new(Some("user@host".parse().unwrap()), false, script_content.to_string(), executor);
 
     context.run(false).unwrap();
 
@@ -73,3 +86,605 @@ fn test_loop_execution() {
     assert!(commands.contains(&"RUN: echo alice".to_string()));
     assert!(commands.contains(&"RUN: echo bob".to_string()));
 }
+
+// Executor whose remote runs fail when the script contains a given marker
+#[derive(Clone)]
+struct FailingExecutor {
+    commands: Arc<Mutex<Vec<String>>>,
+    marker: String,
+    remaining_failures: Arc<Mutex<usize>>,
+}
+
+impl FailingExecutor {
+    fn new(marker: &str, failures: usize) -> Self {
+        Self {
+            commands: Arc::new(Mutex::new(Vec::new())),
+            marker: marker.to_string(),
+            remaining_failures: Arc::new(Mutex::new(failures)),
+        }
+    }
+}
+
+impl RemoteExecutor for FailingExecutor {
+    fn connect(&mut self, _target: &Target) -> Result<(), SeeedError> {
+        Ok(())
+    }
+
+    fn command(&self, command: &str) -> Result<CommandOutput, SeeedError> {
+        self.commands.lock().unwrap().push(command.to_string());
+        Ok(CommandOutput::default())
+    }
+
+    fn run(&self, script: &str, _options: &RunOptions) -> Result<CommandOutput, SeeedError> {
+        self.commands.lock().unwrap().push(format!("RUN: {}", script));
+        let mut remaining_failures = self.remaining_failures.lock().unwrap();
+        if script.contains(&self.marker) && *remaining_failures > 0 {
+            *remaining_failures -= 1;
+            return Ok(CommandOutput {
+                exit_code: 100,
+                stdout: String::new(),
+                stderr: "E: Unable to locate package\n".to_string(),
+            });
+        }
+        Ok(CommandOutput::default())
+    }
+
+    fn upload(&self, _content: &[u8], _dst_path: String, _options: &RunOptions) -> Result<(), SeeedError> {
+        Ok(())
+    }
+}
+
+#[test]
+fn test_failing_remote_stops_execution() {
+    let script_content = "| apt-get install nope\n| echo after\n";
+
+    let mock = FailingExecutor::new("nope", usize::MAX);
+    let executor = Box::new(mock.clone());
+    let mut context = ScriptContext::new(Some("user@host".parse().unwrap()), false, script_content.to_string(), executor);
+
+    let result = context.run(false);
+
+    match result {
+        Err(SeeedError::RemoteCommandFailed { exit_code, statement, stderr }) => {
+            assert_eq!(exit_code, 100);
+            assert_eq!(statement, " apt-get install nope");
+            assert!(stderr.contains("Unable to locate package"));
+        }
+        _ => panic!("Expected RemoteCommandFailed error"),
+    }
+
+    let commands = mock.commands.lock().unwrap();
+    assert_eq!(commands.len(), 1);
+}
+
+#[test]
+fn test_ignore_errors_block_continues() {
+    let script_content = "+ ignore_errors\n| apt-get install nope\n+\n| echo after\n";
+
+    let mock = FailingExecutor::new("nope", usize::MAX);
+    let executor = Box::new(mock.clone());
+    let mut context = ScriptContext::new(Some("user@host".parse().unwrap()), false, script_content.to_string(), executor);
+
+    context.run(false).unwrap();
+
+    let commands = mock.commands.lock().unwrap();
+    assert_eq!(commands.len(), 2);
+    assert_eq!(commands[1], "RUN:  echo after");
+}
+
+#[test]
+fn test_retry_block_until_success() {
+    let script_content = "+ retry=5 delay=0s\n| apt-get install nope\n+\n";
+
+    let mock = FailingExecutor::new("nope", 2);
+    let executor = Box::new(mock.clone());
+    let mut context = ScriptContext::new(Some("user@host".parse().unwrap()), false, script_content.to_string(), executor);
+
+    context.run(false).unwrap();
+
+    let commands = mock.commands.lock().unwrap();
+    assert_eq!(commands.len(), 3);
+}
+
+#[test]
+fn test_retry_block_gives_up() {
+    let script_content = "+ retry=2 delay=0\n| apt-get install nope\n+\n| echo after\n";
+
+    let mock = FailingExecutor::new("nope", usize::MAX);
+    let executor = Box::new(mock.clone());
+    let mut context = ScriptContext::new(Some("user@host".parse().unwrap()), false, script_content.to_string(), executor);
+
+    let result = context.run(false);
+    assert!(matches!(result, Err(SeeedError::RemoteCommandFailed { .. })));
+
+    let commands = mock.commands.lock().unwrap();
+    assert_eq!(commands.len(), 3);
+}
+
+#[test]
+fn test_until_condition_is_retried() {
+    let script_content = "+ until=\"check nope\" retry=3 delay=0s\n| systemctl start nginx\n+\n";
+
+    let mock = FailingExecutor::new("nope", 1);
+    let executor = Box::new(mock.clone());
+    let mut context = ScriptContext::new(Some("user@host".parse().unwrap()), false, script_content.to_string(), executor);
+
+    context.run(false).unwrap();
+
+    let commands = mock.commands.lock().unwrap();
+    assert_eq!(*commands, vec![
+        "RUN:  systemctl start nginx".to_string(),
+        "RUN: check nope".to_string(),
+        "RUN:  systemctl start nginx".to_string(),
+        "RUN: check nope".to_string(),
+    ]);
+}
+
+#[test]
+fn test_capture_single_remote_output() {
+    let script_content = "let kernel = | uname -r\n| echo {{ kernel }} {{ kernel_exit_code }}\n";
+
+    let mock = MockExecutor::with_stdout("6.1.0-18-amd64\n");
+    let executor = Box::new(mock.clone());
+    let mut context = ScriptContext::new(Some("user@host".parse().unwrap()), false, script_content.to_string(), executor);
+
+    context.run(false).unwrap();
+
+    let commands = mock.commands.lock().unwrap();
+    assert_eq!(commands[0], "RUN:  uname -r");
+    assert_eq!(commands[1], "RUN:  echo 6.1.0-18-amd64 0");
+}
+
+#[test]
+fn test_capture_failed_block_output() {
+    let script_content = "let info = + ignore_errors\n| dpkg -s nope\n+\n| echo {{ info_exit_code }} {{ info_stderr }}\n";
+
+    let mock = FailingExecutor::new("nope", usize::MAX);
+    let executor = Box::new(mock.clone());
+    let mut context = ScriptContext::new(Some("user@host".parse().unwrap()), false, script_content.to_string(), executor);
+
+    context.run(false).unwrap();
+
+    let commands = mock.commands.lock().unwrap();
+    assert_eq!(commands[1], "RUN:  echo 100 E: Unable to locate package");
+}
+
+#[test]
+fn test_if_else_execution() {
+    let script_content = "let install = false\nlet users = [\"alice\"]\nif $install {\n| echo install\n} else if $users {\n| echo users\n} else {\n| echo none\n}\n";
+
+    let mock = MockExecutor::new();
+    let executor = Box::new(mock.clone());
+    let mut context = ScriptContext::new(Some("user@host".parse().unwrap()), false, script_content.to_string(), executor);
+
+    context.run(false).unwrap();
+
+    let commands = mock.commands.lock().unwrap();
+    assert_eq!(*commands, vec!["RUN:  echo users".to_string()]);
+}
+
+#[test]
+fn test_syntax_error_in_nested_block() {
+    let script_content = "if true {\nthis is not valid\n}\n";
+
+    let mock = MockExecutor::new();
+    let executor = Box::new(mock.clone());
+    let mut context = ScriptContext::new(Some("user@host".parse().unwrap()), false, script_content.to_string(), executor);
+
+    match context.run(false) {
+        Err(SeeedError::ParseError { line, .. }) => assert_eq!(line, 2),
+        _ => panic!("Expected ParseError"),
+    }
+}
+
+#[test]
+fn test_condition_with_operators() {
+    let script_content = "let env = \"prod\"\nlet replicas = 3\nlet name = \"web-\" + $replicas\nif $env == \"prod\" && $replicas > 1 && !($name in [\"db\"]) {\n| echo {{ name }}\n}\n";
+
+    let mock = MockExecutor::new();
+    let executor = Box::new(mock.clone());
+    let mut context = ScriptContext::new(Some("user@host".parse().unwrap()), false, script_content.to_string(), executor);
+
+    context.run(false).unwrap();
+
+    let commands = mock.commands.lock().unwrap();
+    assert_eq!(*commands, vec!["RUN:  echo web-3".to_string()]);
+}
+
+#[test]
+fn test_object_access_and_iteration() {
+    let script_content = "let users = [{ name: \"alice\", shell: \"zsh\" }, { name: \"bob\", shell: \"bash\" }]\nlet last = $users[-1]\n| echo {{ last.name }} {{ users[0][\"shell\"] }}\nfor i, user in $users {\n| echo {{ i }} {{ user.name }}\n}\nfor key, value in $users[0] {\n| echo {{ key }}={{ value }}\n}\n";
+
+    let mock = MockExecutor::new();
+    let executor = Box::new(mock.clone());
+    let mut context = ScriptContext::new(Some("user@host".parse().unwrap()), false, script_content.to_string(), executor);
+
+    context.run(false).unwrap();
+
+    let commands = mock.commands.lock().unwrap();
+    assert_eq!(*commands, vec![
+        "RUN:  echo bob zsh".to_string(),
+        "RUN:  echo 0 alice".to_string(),
+        "RUN:  echo 1 bob".to_string(),
+        "RUN:  echo name=alice".to_string(),
+        "RUN:  echo shell=zsh".to_string(),
+    ]);
+}
+
+#[test]
+fn test_missing_object_key() {
+    let script_content = "let user = { name: \"alice\" }\nlet shell = $user.shell\n";
+
+    let mock = MockExecutor::new();
+    let executor = Box::new(mock.clone());
+    let mut context = ScriptContext::new(Some("user@host".parse().unwrap()), false, script_content.to_string(), executor);
+
+    assert!(matches!(context.run(false), Err(SeeedError::KeyNotFound(key)) if key == "shell"));
+}
+
+#[test]
+fn test_native_values_in_templates() {
+    let script_content = "let packages = [\"nginx\", \"git\"]\nlet port = 8080\nlet tls = false\n+\n| apt-get install {{ packages | join(\" \") }}\n| {% for p in packages %}echo {{ p }};{% endfor %}\n| echo {{ port + 1 }}{% if tls %} tls{% endif %}\n+\n";
+
+    let mock = MockExecutor::new();
+    let executor = Box::new(mock.clone());
+    let mut context = ScriptContext::new(Some("user@host".parse().unwrap()), false, script_content.to_string(), executor);
+
+    context.run(false).unwrap();
+
+    let commands = mock.commands.lock().unwrap();
+    assert_eq!(commands[0], "RUN:  apt-get install nginx git\n echo nginx;echo git;\n echo 8081");
+}
+
+#[test]
+fn test_builtin_functions_return_values() {
+    let dir = std::env::temp_dir().join(format!("seeed_test_{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let template_path = dir.join("nginx.j2");
+    std::fs::write(&template_path, "listen {{ port }};").unwrap();
+
+    let script_content = format!(
+        "let port = 8080\nlet raw = read_file(\"{path}\")\nfor dst in [\"/etc/nginx/nginx.conf\"] {{\n    upload(template(\"{path}\"), $dst)\n}}\nupload($raw, \"/tmp/raw\")\n",
+        path = template_path.display()
+    );
+
+    let mock = MockExecutor::new();
+    let executor = Box::new(mock.clone());
+    let mut context = ScriptContext::new(Some("user@host".parse().unwrap()), false, script_content, executor);
+
+    context.run(false).unwrap();
+
+    let uploads = mock.uploads.lock().unwrap();
+    assert_eq!(*uploads, vec![
+        ("listen 8080;".to_string(), "/etc/nginx/nginx.conf".to_string()),
+        ("listen {{ port }};".to_string(), "/tmp/raw".to_string()),
+    ]);
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_user_defined_functions() {
+    let script_content = "let group = \"sudo\"\ncreate_user(\"alice\", \"zsh\")\n\nfn create_user(name, shell) {\n    let home = home_of($name)\n    +\n    | useradd -m -d {{ home }} -s /bin/{{ shell }} -G {{ group }} {{ name }}\n    +\n}\n\nfn home_of(name) {\n    if $name == \"root\" {\n        return \"/root\"\n    }\n    return \"/home/\" + $name\n}\n\ncreate_user(\"root\", \"bash\")\n";
+
+    let mock = MockExecutor::new();
+    let executor = Box::new(mock.clone());
+    let mut context = ScriptContext::new(Some("user@host".parse().unwrap()), false, script_content.to_string(), executor);
+
+    context.run(false).unwrap();
+
+    let commands = mock.commands.lock().unwrap();
+    assert_eq!(*commands, vec![
+        "RUN:  useradd -m -d /home/alice -s /bin/zsh -G sudo alice".to_string(),
+        "RUN:  useradd -m -d /root -s /bin/bash -G sudo root".to_string(),
+    ]);
+}
+
+#[test]
+fn test_function_scope_is_local() {
+    let script_content = "fn set_name() {\n    let name = \"inner\"\n}\nlet name = \"outer\"\nset_name()\n| echo {{ name }}\n";
+
+    let mock = MockExecutor::new();
+    let executor = Box::new(mock.clone());
+    let mut context = ScriptContext::new(Some("user@host".parse().unwrap()), false, script_content.to_string(), executor);
+
+    context.run(false).unwrap();
+
+    let commands = mock.commands.lock().unwrap();
+    assert_eq!(*commands, vec!["RUN:  echo outer".to_string()]);
+}
+
+fn run_script(script_content: &str) -> Result<(), SeeedError> {
+    let executor = Box::new(MockExecutor::new());
+    let mut context = ScriptContext::new(Some("user@host".parse().unwrap()), false, script_content.to_string(), executor);
+    context.run(false)
+}
+
+#[test]
+fn test_function_wrong_arg_count() {
+    let result = run_script("fn f(a) {\n}\nf(1, 2)\n");
+    assert!(matches!(result, Err(SeeedError::WrongArgCount(1, 2))), "{:?}", result);
+}
+
+#[test]
+fn test_unknown_function() {
+    let result = run_script("missing()\n");
+    assert!(matches!(result, Err(SeeedError::UnknownFunction(ref name)) if name == "missing"), "{:?}", result);
+}
+
+#[test]
+fn test_return_outside_function() {
+    let result = run_script("return 1\n");
+    assert!(matches!(result, Err(SeeedError::ReturnOutsideFunction)), "{:?}", result);
+}
+
+#[test]
+fn test_function_call_depth() {
+    let result = run_script("fn f() {\n    f()\n}\nf()\n");
+    assert!(matches!(result, Err(SeeedError::CallDepthExceeded(_))), "{:?}", result);
+}
+
+#[test]
+fn test_include_files() {
+    let dir = std::env::temp_dir().join(format!("seeed_include_{}", std::process::id()));
+    std::fs::create_dir_all(dir.join("lib")).unwrap();
+    std::fs::write(dir.join("lib/users.seeed"), "include \"common.seeed\"\nfn create_user(name) {\n    | useradd {{ name }} -G {{ group }}\n}\n").unwrap();
+    std::fs::write(dir.join("lib/common.seeed"), "let group = \"sudo\"\n").unwrap();
+    let main_path = dir.join("main.seeed");
+    let script_content = "include \"lib/users.seeed\"\ncreate_user(\"alice\")\n";
+
+    let mock = MockExecutor::new();
+    let executor = Box::new(mock.clone());
+    let mut context = ScriptContext::new(Some("user@host".parse().unwrap()), false, script_content.to_string(), executor);
+    context.set_script_path(&main_path);
+
+    context.run(false).unwrap();
+
+    let commands = mock.commands.lock().unwrap();
+    assert_eq!(*commands, vec!["RUN:  useradd alice -G sudo".to_string()]);
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_read_files_relative_to_script() {
+    let dir = std::env::temp_dir().join(format!("seeed_relative_{}", std::process::id()));
+    std::fs::create_dir_all(dir.join("nginx")).unwrap();
+    std::fs::write(dir.join("motd.txt"), "welcome").unwrap();
+    std::fs::write(dir.join("nginx/site.seeed"), "upload(template(\"site.j2\"), \"/etc/nginx/sites-enabled/app\")\nupload(\"site.j2\", \"/tmp/site.j2\")\n").unwrap();
+    std::fs::write(dir.join("nginx/site.j2"), "listen {{ port }};").unwrap();
+    let script_content = "let port = 80\nupload(read_file(\"motd.txt\"), \"/etc/motd\")\ninclude \"nginx/site.seeed\"\n";
+
+    let mock = MockExecutor::new();
+    let executor = Box::new(mock.clone());
+    let mut context = ScriptContext::new(Some("user@host".parse().unwrap()), false, script_content.to_string(), executor);
+    context.set_script_path(&dir.join("main.seeed"));
+
+    context.run(false).unwrap();
+
+    let uploads = mock.uploads.lock().unwrap();
+    assert_eq!(*uploads, vec![
+        ("welcome".to_string(), "/etc/motd".to_string()),
+        ("listen 80;".to_string(), "/etc/nginx/sites-enabled/app".to_string()),
+        ("listen {{ port }};".to_string(), "/tmp/site.j2".to_string()),
+    ]);
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+/// runs a script in a directory of its own, holding the given files
+fn run_script_with_files(name: &str, files: &[(&str, &str)], script_content: &str) -> Result<(), SeeedError> {
+    let dir = std::env::temp_dir().join(format!("seeed_{}_{}", name, std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    for (file, contents) in files {
+        std::fs::write(dir.join(file), contents).unwrap();
+    }
+
+    let executor = Box::new(MockExecutor::new());
+    let mut context = ScriptContext::new(Some("user@host".parse().unwrap()), false, script_content.to_string(), executor);
+    context.set_script_path(&dir.join("main.seeed"));
+    let result = context.run(false);
+
+    std::fs::remove_dir_all(&dir).unwrap();
+    result
+}
+
+#[test]
+fn test_include_cycle() {
+    let files = [("a.seeed", "include \"b.seeed\"\n"), ("b.seeed", "include \"a.seeed\"\n")];
+    let result = run_script_with_files("include_cycle", &files, "include \"a.seeed\"\n");
+    assert!(matches!(result, Err(SeeedError::IncludeCycle(ref chain)) if chain.ends_with("a.seeed")), "{:?}", result);
+}
+
+#[test]
+fn test_include_parse_error() {
+    let files = [("broken.seeed", "let a = 1\nfor x in {\n")];
+    let result = run_script_with_files("include_parse_error", &files, "include \"broken.seeed\"\n");
+    assert!(matches!(result, Err(SeeedError::ParseError { ref file, line: 2, .. }) if file.ends_with("broken.seeed")), "{:?}", result);
+}
+
+#[test]
+fn test_include_missing_file() {
+    let result = run_script_with_files("include_missing_file", &[], "include \"missing.seeed\"\n");
+    assert!(matches!(result, Err(SeeedError::IncludeFailed(..))), "{:?}", result);
+}
+
+#[test]
+fn test_block_shell_override() {
+    let script_content = "+ shell=python3\n| print(\"hello\")\n+\n| echo done\n";
+
+    let mock = MockExecutor::new();
+    let executor = Box::new(mock.clone());
+    let mut context = ScriptContext::new(Some("user@host".parse().unwrap()), false, script_content.to_string(), executor);
+
+    context.run(false).unwrap();
+
+    let run_options = mock.run_options.lock().unwrap();
+    assert_eq!(*run_options, vec![
+        RunOptions { shell: Some("python3".to_string()), sudo: Some(false), ..RunOptions::default() },
+        RunOptions { sudo: Some(false), ..RunOptions::default() },
+    ]);
+}
+
+#[test]
+fn test_become_user_block() {
+    let script_content = "+ become_user=postgres until=\"pg_isready\"\n| createdb app\n+\n";
+
+    let mock = MockExecutor::new();
+    let executor = Box::new(mock.clone());
+    let mut context = ScriptContext::new(Some("user@host".parse().unwrap()), false, script_content.to_string(), executor);
+
+    context.run(false).unwrap();
+
+    // the until check runs as the same user as the block
+    let postgres = RunOptions { become_user: Some("postgres".to_string()), sudo: Some(false), ..RunOptions::default() };
+    assert_eq!(*mock.run_options.lock().unwrap(), vec![postgres.clone(), postgres]);
+}
+
+#[test]
+fn test_become_user_upload() {
+    let script_content = "let hba = <<<EOF\nlocal all all peer\nEOF>>>\nupload($hba, \"/etc/postgresql/pg_hba.conf\", { become_user: \"postgres\" })\nupload($hba, \"/tmp/pg_hba.conf\")\n";
+
+    let mock = MockExecutor::new();
+    let executor = Box::new(mock.clone());
+    let mut context = ScriptContext::new(Some("user@host".parse().unwrap()), false, script_content.to_string(), executor);
+
+    context.run(false).unwrap();
+
+    let postgres = RunOptions { become_user: Some("postgres".to_string()), sudo: Some(false), ..RunOptions::default() };
+    assert_eq!(*mock.upload_options.lock().unwrap(), vec![postgres, RunOptions { sudo: Some(false), ..RunOptions::default() }]);
+}
+
+#[test]
+fn test_upload_unknown_option() {
+    let result = run_script("upload(\"x\", \"/tmp/x\", { owner: \"root\" })\n");
+    assert!(matches!(result, Err(SeeedError::BadArgType(_))), "{:?}", result);
+}
+
+#[test]
+fn test_upload_wrong_arg_count() {
+    let result = run_script("upload(\"x\")\n");
+    assert!(matches!(result, Err(SeeedError::WrongArgRange(2, 3, 1))), "{:?}", result);
+}
+
+#[test]
+fn test_sudo_scopes() {
+    let script_content = "| make\n+ sudo\n| make install\n+\nsudo {\n    | systemctl restart app\n    upload($unit, \"/etc/systemd/system/app.service\")\n    + nosudo\n    | rm -rf build\n    +\n}\n| echo done\n";
+
+    let mock = MockExecutor::new();
+    let executor = Box::new(mock.clone());
+    let mut context = ScriptContext::new(Some("user@host".parse().unwrap()), false, format!("let unit = <<<EOF\n[Unit]\nEOF>>>\n{}", script_content), executor);
+
+    context.run(false).unwrap();
+
+    let sudo = mock.run_options.lock().unwrap().iter().map(|options| options.sudo).collect::<Vec<_>>();
+    assert_eq!(sudo, vec![Some(false), Some(true), Some(true), Some(false), Some(false)]);
+    assert_eq!(mock.upload_options.lock().unwrap()[0].sudo, Some(true));
+
+    // the script default applies outside of the scopes
+    let mock = MockExecutor::new();
+    let executor = Box::new(mock.clone());
+    let mut context = ScriptContext::new(Some("user@host".parse().unwrap()), true, "| apt-get update\n+ nosudo\n| make\n+\n".to_string(), executor);
+
+    context.run(false).unwrap();
+
+    let sudo = mock.run_options.lock().unwrap().iter().map(|options| options.sudo).collect::<Vec<_>>();
+    assert_eq!(sudo, vec![Some(true), Some(false)]);
+}
+
+// Executor whose connection drops after each run, to check the reconnection between statements
+#[derive(Clone)]
+struct DroppingExecutor {
+    connections: Arc<Mutex<usize>>,
+    alive: Arc<Mutex<bool>>,
+}
+
+impl RemoteExecutor for DroppingExecutor {
+    fn connect(&mut self, _target: &Target) -> Result<(), SeeedError> {
+        *self.connections.lock().unwrap() += 1;
+        *self.alive.lock().unwrap() = true;
+        Ok(())
+    }
+
+    fn command(&self, _command: &str) -> Result<CommandOutput, SeeedError> {
+        Ok(CommandOutput::default())
+    }
+
+    fn run(&self, _script: &str, _options: &RunOptions) -> Result<CommandOutput, SeeedError> {
+        *self.alive.lock().unwrap() = false;
+        Ok(CommandOutput::default())
+    }
+
+    fn upload(&self, _content: &[u8], _dst_path: String, _options: &RunOptions) -> Result<(), SeeedError> {
+        Ok(())
+    }
+
+    fn is_alive(&self) -> bool {
+        *self.alive.lock().unwrap()
+    }
+}
+
+#[test]
+fn test_reconnect_between_statements() {
+    let executor = DroppingExecutor {
+        connections: Arc::new(Mutex::new(0)),
+        alive: Arc::new(Mutex::new(false)),
+    };
+    let mut context = ScriptContext::new(Some("user@host".parse().unwrap()), false, "| apt-get update\n| apt-get upgrade -y\n".to_string(), Box::new(executor.clone()));
+
+    context.run(false).unwrap();
+
+    assert_eq!(*executor.connections.lock().unwrap(), 2);
+}
+
+#[test]
+fn test_block_timeout() {
+    let mock = MockExecutor::new();
+    let executor = Box::new(mock.clone());
+    let mut context = ScriptContext::new(Some("user@host".parse().unwrap()), false, "+ timeout=10m\n| apt-get upgrade -y\n+\n".to_string(), executor);
+
+    context.run(false).unwrap();
+
+    assert_eq!(mock.run_options.lock().unwrap()[0].timeout, Some(std::time::Duration::from_secs(600)));
+}
+
+#[test]
+fn test_env_file_secrets() {
+    let env_file = std::env::temp_dir().join(format!("seeed_env_{}", std::process::id()));
+    std::fs::write(&env_file, "APP_PORT=8080\nSEEED_SSH_PASSWORD=hunter2\nSEEED_SUDO_PASSWORD=hunter3\n").unwrap();
+
+    let mock = MockExecutor::new();
+    let script = "| listen {{ APP_PORT }}\nlet password = $SEEED_SSH_PASSWORD\n";
+    let mut context = ScriptContext::new(Some("user@host".parse().unwrap()), false, script.to_string(), Box::new(mock.clone()));
+    context.load_env(env_file.to_str().unwrap()).unwrap();
+    let result = context.run(false);
+    std::fs::remove_file(&env_file).unwrap();
+
+    // the other variables of the env file are defined, but not the password
+    assert!(mock.commands.lock().unwrap()[0].contains("listen 8080"));
+    assert!(matches!(result, Err(SeeedError::UndefinedVar(name)) if name == "SEEED_SSH_PASSWORD"));
+
+    let mut context = ScriptContext::new(Some("user@host".parse().unwrap()), false, "let password = $SEEED_SUDO_PASSWORD\n".to_string(), Box::new(MockExecutor::new()));
+    std::fs::write(&env_file, "SEEED_SUDO_PASSWORD=hunter3\n").unwrap();
+    context.load_env(env_file.to_str().unwrap()).unwrap();
+    let result = context.run(false);
+    std::fs::remove_file(&env_file).unwrap();
+    assert!(matches!(result, Err(SeeedError::UndefinedVar(name)) if name == "SEEED_SUDO_PASSWORD"));
+}
+
+#[test]
+fn test_env_file_numbers() {
+    let env_file = std::env::temp_dir().join(format!("seeed_env_numbers_{}", std::process::id()));
+    std::fs::write(&env_file, "COUNT=2\nNAME=web\n").unwrap();
+
+    let mock = MockExecutor::new();
+    let script = "let next = $COUNT + 1\nlet previous = $COUNT - 1\nlet host = $NAME + $COUNT\n| echo {{ next }} {{ previous }} {{ host }}\n";
+    let mut context = ScriptContext::new(Some("user@host".parse().unwrap()), false, script.to_string(), Box::new(mock.clone()));
+    context.load_env(env_file.to_str().unwrap()).unwrap();
+    let result = context.run(false);
+    std::fs::remove_file(&env_file).unwrap();
+    result.unwrap();
+
+    // a number read as a string is added, a name is concatenated
+    assert!(mock.commands.lock().unwrap()[0].contains("echo 3 1 web2"));
+}
@@ -0,0 +1,122 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use seeed::error::SeeedError;
+use seeed::inventory::{Inventory, InventoryHost};
+use seeed::parser::Literal;
+use seeed::script::ScriptContext;
+use seeed::sshclient::{CommandOutput, RemoteExecutor, RunOptions};
+use seeed::target::Target;
+
+const INVENTORY: &str = r#"
+[vars]
+env = "production"
+http_port = 8000
+
+[hosts.web1]
+target = "deploy@10.0.0.11"
+http_port = 8080
+
+[hosts]
+web2 = "deploy@10.0.0.12:2222"
+db1 = {}
+
+[groups.web]
+hosts = ["web1", "web2"]
+vars = { http_port = 80, packages = ["nginx", "certbot"] }
+
+[groups.db]
+hosts = ["db1"]
+"#;
+
+struct MockExecutor {
+    scripts: Rc<RefCell<Vec<String>>>,
+}
+
+impl RemoteExecutor for MockExecutor {
+    fn connect(&mut self, _target: &Target) -> Result<(), SeeedError> { Ok(()) }
+    fn command(&self, _command: &str) -> Result<CommandOutput, SeeedError> { Ok(CommandOutput::default()) }
+    fn run(&self, script: &str, _options: &RunOptions) -> Result<CommandOutput, SeeedError> {
+        self.scripts.borrow_mut().push(script.to_string());
+        Ok(CommandOutput::default())
+    }
+    fn upload(&self, _content: &[u8], _dst_path: String, _options: &RunOptions) -> Result<(), SeeedError> { Ok(()) }
+}
+
+fn variable<'a>(host: &'a InventoryHost, name: &str) -> Option<&'a Literal> {
+    host.variables.iter().find(|(variable, _)| variable == name).map(|(_, value)| value)
+}
+
+fn names(hosts: &[InventoryHost]) -> Vec<&str> {
+    hosts.iter().map(|host| host.name.as_str()).collect()
+}
+
+#[test]
+fn test_inventory_hosts_and_variables() {
+    let inventory = Inventory::parse(INVENTORY).unwrap();
+    let hosts = inventory.select(None).unwrap();
+    assert_eq!(names(&hosts), vec!["web1", "web2", "db1"]);
+
+    let web1 = &hosts[0];
+    assert_eq!(web1.target, "deploy@10.0.0.11".parse().unwrap());
+    assert_eq!(web1.groups, vec!["web".to_string()]);
+    // host variables win over group variables, which win over global ones
+    assert!(matches!(variable(web1, "http_port"), Some(Literal::Integer(8080))));
+    assert!(matches!(variable(web1, "env"), Some(Literal::String(env)) if env == "production"));
+    assert!(matches!(variable(web1, "inventory_hostname"), Some(Literal::String(name)) if name == "web1"));
+
+    let web2 = &hosts[1];
+    assert_eq!(web2.target.port, Some(2222));
+    assert!(matches!(variable(web2, "http_port"), Some(Literal::Integer(80))));
+    assert!(matches!(variable(web2, "packages"), Some(Literal::Array(packages)) if packages.len() == 2));
+
+    // the target defaults to the host name
+    let db1 = &hosts[2];
+    assert_eq!(db1.target, "db1".parse().unwrap());
+    assert!(matches!(variable(db1, "http_port"), Some(Literal::Integer(8000))));
+}
+
+#[test]
+fn test_inventory_limit() {
+    let inventory = Inventory::parse(INVENTORY).unwrap();
+    assert_eq!(names(&inventory.select(Some("web")).unwrap()), vec!["web1", "web2"]);
+    assert_eq!(names(&inventory.select(Some("db1,web2")).unwrap()), vec!["web2", "db1"]);
+    assert_eq!(names(&inventory.select(Some("web*")).unwrap()), vec!["web1", "web2"]);
+    assert_eq!(names(&inventory.select(Some("all,!web1")).unwrap()), vec!["web2", "db1"]);
+    assert_eq!(names(&inventory.select(Some("!db")).unwrap()), vec!["web1", "web2"]);
+    assert!(matches!(inventory.select(Some("cache")), Err(SeeedError::Inventory(_))));
+}
+
+#[test]
+fn test_invalid_inventories() {
+    for contents in [
+        "[hosts.web1]\ntarget = \"deploy@\"\n",
+        "[hosts.web1]\ntarget = 42\n",
+        "[groups.web]\nhosts = [\"web1\"]\n",
+        "[groups.web]\nmembers = []\n",
+        "[servers.web1]\n",
+        "[hosts\n",
+    ] {
+        assert!(Inventory::parse(contents).is_err(), "{} should be rejected", contents);
+    }
+}
+
+#[test]
+fn test_script_with_inventory_variables() {
+    let inventory = Inventory::parse(INVENTORY).unwrap();
+    let script = "| listen {{ http_port }} on {{ inventory_hostname }}\n";
+
+    for host in inventory.select(Some("web")).unwrap() {
+        let scripts = Rc::new(RefCell::new(Vec::new()));
+        let executor = Box::new(MockExecutor { scripts: scripts.clone() });
+        let mut context = ScriptContext::new(Some(host.target.clone()), false, script.to_string(), executor);
+        context.set_variables(&host.variables);
+        context.run(false).unwrap();
+
+        let expected = match host.name.as_str() {
+            "web1" => "listen 8080 on web1",
+            _ => "listen 80 on web2",
+        };
+        assert!(scripts.borrow()[0].contains(expected), "{:?}", scripts.borrow());
+    }
+}
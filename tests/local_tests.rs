@@ -0,0 +1,77 @@
+use std::time::Duration;
+
+use seeed::error::SeeedError;
+use seeed::local::LocalExecutor;
+use seeed::script::ScriptContext;
+use seeed::sshclient::{RemoteExecutor, RunOptions, SshOptions};
+use seeed::target::Target;
+
+fn executor() -> LocalExecutor {
+    LocalExecutor::new(false, "/bin/sh".to_string(), SshOptions::default())
+}
+
+#[test]
+fn test_local_target() {
+    assert!("local".parse::<Target>().unwrap().is_local());
+    assert!(!"root@local".parse::<Target>().unwrap().is_local());
+    assert!(!"local:2222".parse::<Target>().unwrap().is_local());
+    assert!(!"localhost".parse::<Target>().unwrap().is_local());
+}
+
+#[test]
+fn test_local_run() {
+    let executor = executor();
+
+    let output = executor.run("echo hello\necho oops >&2\nexit 3\n", &RunOptions::default()).unwrap();
+    assert_eq!(output.exit_code, 3);
+    assert_eq!(output.stdout, "hello\n");
+    assert_eq!(output.stderr, "oops\n");
+
+    let options = RunOptions { shell: Some("cat".to_string()), ..RunOptions::default() };
+    let output = executor.run("not a script", &options).unwrap();
+    assert_eq!(output.stdout, "not a script");
+
+    let output = executor.command("printf '%s' $((6 * 7))").unwrap();
+    assert_eq!(output.stdout, "42");
+}
+
+#[test]
+fn test_local_timeout() {
+    let options = RunOptions { timeout: Some(Duration::from_millis(200)), ..RunOptions::default() };
+    let result = executor().run("sleep 5\n", &options);
+    assert!(matches!(result, Err(SeeedError::CommandTimeout(_))));
+}
+
+#[test]
+fn test_local_timeout_kills_children() {
+    let pid_file = std::env::temp_dir().join(format!("seeed_local_pid_{}", std::process::id()));
+    let script = format!("sleep 30 &\necho $! > {}\nwait\n", pid_file.display());
+    let options = RunOptions { timeout: Some(Duration::from_millis(300)), ..RunOptions::default() };
+    assert!(matches!(executor().run(&script, &options), Err(SeeedError::CommandTimeout(_))));
+
+    // the background process is gone, or left as a zombie when nothing reaps it
+    std::thread::sleep(Duration::from_millis(100));
+    let pid = std::fs::read_to_string(&pid_file).unwrap();
+    let stat = std::fs::read_to_string(format!("/proc/{}/stat", pid.trim())).unwrap_or_default();
+    assert!(stat.is_empty() || stat.contains(") Z "), "{}", stat);
+    std::fs::remove_file(&pid_file).unwrap();
+}
+
+#[test]
+fn test_local_script() {
+    let directory = std::env::temp_dir().join(format!("seeed_local_{}", std::process::id()));
+    std::fs::create_dir_all(&directory).unwrap();
+    let destination = directory.join("motd");
+
+    let script = format!(
+        "let kernel = | uname -s\nlet motd = <<<EOF\nwelcome on {{{{ kernel }}}}\nEOF>>>\nupload($motd, \"{}\")\n| grep -q welcome {}\n",
+        destination.display(),
+        destination.display()
+    );
+    let mut context = ScriptContext::new(Some("local".parse().unwrap()), false, script, Box::new(executor()));
+    context.run(false).unwrap();
+
+    let content = std::fs::read_to_string(&destination).unwrap();
+    assert!(content.trim().starts_with("welcome on "), "{}", content);
+    std::fs::remove_dir_all(&directory).unwrap();
+}
@@ -0,0 +1,56 @@
+use seeed::error::SeeedError;
+use seeed::operators::{binary, equals, unary};
+use seeed::parser::{BinaryOperator, Literal, UnaryOperator};
+
+fn string(s: &str) -> Literal {
+    Literal::String(s.to_string())
+}
+
+#[test]
+fn test_equality_coerces_numeric_strings() {
+    assert!(equals(&Literal::Integer(3), &string("3")));
+    assert!(equals(&string("web"), &Literal::HereDoc("web".to_string())));
+    assert!(!equals(&Literal::Bool(true), &string("true")));
+}
+
+#[test]
+fn test_arithmetic() {
+    assert!(matches!(binary(BinaryOperator::Multiply, Literal::Integer(6), Literal::Integer(7)), Ok(Literal::Integer(42))));
+    assert!(matches!(binary(BinaryOperator::Modulo, Literal::Integer(7), string("4")), Ok(Literal::Integer(3))));
+    assert!(matches!(binary(BinaryOperator::Divide, Literal::Integer(1), Literal::Integer(0)), Err(SeeedError::DivisionByZero)));
+    assert!(matches!(binary(BinaryOperator::Subtract, string("a"), Literal::Integer(1)), Err(SeeedError::TypeError(_))));
+    assert!(matches!(unary(UnaryOperator::Negate, Literal::Integer(5)), Ok(Literal::Integer(-5))));
+}
+
+#[test]
+fn test_concatenation() {
+    match binary(BinaryOperator::Add, string("web-"), Literal::Integer(1)) {
+        Ok(Literal::String(s)) => assert_eq!(s, "web-1"),
+        other => panic!("Expected a string, got {:?}", other),
+    }
+    match binary(BinaryOperator::Add, Literal::Array(vec![string("a")]), Literal::Array(vec![string("b")])) {
+        Ok(Literal::Array(items)) => assert_eq!(items.len(), 2),
+        other => panic!("Expected an array, got {:?}", other),
+    }
+}
+
+#[test]
+fn test_comparison_and_membership() {
+    assert!(matches!(binary(BinaryOperator::Less, string("9"), string("10")), Ok(Literal::Bool(true))));
+    assert!(matches!(binary(BinaryOperator::Less, string("b"), string("a")), Ok(Literal::Bool(false))));
+    assert!(matches!(binary(BinaryOperator::In, string("git"), Literal::Array(vec![string("nginx"), string("git")])), Ok(Literal::Bool(true))));
+    assert!(matches!(binary(BinaryOperator::In, string("bun"), string("ubuntu")), Ok(Literal::Bool(true))));
+}
+
+#[test]
+fn test_integer_overflow() {
+    assert!(matches!(binary(BinaryOperator::Add, Literal::Integer(i64::MAX), Literal::Integer(1)), Err(SeeedError::TypeError(_))));
+    assert!(matches!(unary(UnaryOperator::Negate, Literal::Integer(i64::MIN)), Err(SeeedError::TypeError(_))));
+    assert!(matches!(unary(UnaryOperator::Negate, Literal::Integer(i64::MAX)), Ok(Literal::Integer(i)) if i == -i64::MAX));
+}
+
+#[test]
+fn test_numeric_strings_are_added() {
+    assert!(matches!(binary(BinaryOperator::Add, string("2"), Literal::Integer(1)), Ok(Literal::Integer(3))));
+    assert!(matches!(binary(BinaryOperator::Add, string("2"), string("3")), Ok(Literal::Integer(5))));
+}
@@ -1,16 +1,18 @@
-use seeed::parser::{script_parser, Statement, Expression, Literal};
+use seeed::parser::{parse_duration, script_parser, BinaryOperator, BlockOptions, Statement, Expression, Literal, UnaryOperator};
+use std::time::Duration;
 use seeed::script::ScriptContext;
-use seeed::sshclient::RemoteExecutor;
+use seeed::target::Target;
+use seeed::sshclient::{CommandOutput, RemoteExecutor, RunOptions};
 use seeed::error::SeeedError;
 
 // Mock executor for parser tests (though parser itself doesn't use executor, 
 // ScriptContext might if we test evaluation)
 struct MockExecutor;
 impl RemoteExecutor for MockExecutor {
-    fn connect(&mut self, _target: &str) -> Result<(), SeeedError> { Ok(()) }
-    fn command(&self, _command: &str) -> Result<(), SeeedError> { Ok(()) }
-    fn run(&self, _script: &str) -> Result<(), SeeedError> { Ok(()) }
-    fn upload(&self, _content: &str, _dst_path: String) -> Result<(), SeeedError> { Ok(()) }
+    fn connect(&mut self, _target: &Target) -> Result<(), SeeedError> { Ok(()) }
+    fn command(&self, _command: &str) -> Result<CommandOutput, SeeedError> { Ok(CommandOutput::default()) }
+    fn run(&self, _script: &str, _options: &RunOptions) -> Result<CommandOutput, SeeedError> { Ok(CommandOutput::default()) }
+    fn upload(&self, _content: &[u8], _dst_path: String, _options: &RunOptions) -> Result<(), SeeedError> { Ok(()) }
 }
 
 #[test]
@@ -35,7 +37,7 @@ fn test_parse_and_evaluate_string_interpolation() {
     let script_content = "let name = \"world\"\nlet msg = \"hello {{ name }}\"\n";
     
     let executor = Box::new(MockExecutor);
-    let mut context = ScriptContext::new("user@host".to_string(), false, script_content.to_string(), executor);
+    let mut context = ScriptContext::new(Some("user@host".parse().unwrap()), false, script_content.to_string(), executor);
     
     context.run(false).unwrap();
     
@@ -43,3 +45,244 @@ fn test_parse_and_evaluate_string_interpolation() {
     // Ideally we should add a getter for testing, or use reflection/debug output.
     // For now, let's verify it doesn't crash.
 }
+
+#[test]
+fn test_parse_remote_block_options() {
+    let script = "+ retry=5 delay=3s until=\"test -f /tmp/done\" ignore_errors\n| apt-get update\n+\n";
+    let ast = script_parser().parse(script.as_bytes()).unwrap();
+    assert_eq!(ast.statements.len(), 1);
+    match &ast.statements[0] {
+        Statement::Remote(options, lines) => {
+            assert_eq!(options, &BlockOptions {
+                ignore_errors: true,
+                retry: Some(5),
+                delay: Some(Duration::from_secs(3)),
+                until: Some("test -f /tmp/done".to_string()),
+                ..BlockOptions::default()
+            });
+            assert_eq!(lines, &vec![" apt-get update".to_string()]);
+        }
+        _ => panic!("Expected remote block statement"),
+    }
+}
+
+#[test]
+fn test_parse_remote_block_unknown_option() {
+    let script = "+ retyr=5\n| apt-get update\n+\n";
+    let ast = script_parser().parse(script.as_bytes()).unwrap();
+    match &ast.statements[0] {
+        Statement::Error(message, _) => assert!(message.contains("retyr")),
+        _ => panic!("Expected error statement"),
+    }
+}
+
+#[test]
+fn test_parse_duration() {
+    assert_eq!(parse_duration("500ms"), Ok(Duration::from_millis(500)));
+    assert_eq!(parse_duration("2m"), Ok(Duration::from_secs(120)));
+    assert_eq!(parse_duration("1h"), Ok(Duration::from_secs(3600)));
+    assert!(parse_duration("3d").is_err());
+    assert!(parse_duration(&format!("{}h", u64::MAX / 60)).is_err());
+}
+
+#[test]
+fn test_parse_captured_block_invalid_option() {
+    let script = "let updated = + retry=abc\n| apt-get update\n+\n";
+    let ast = script_parser().parse(script.as_bytes()).unwrap();
+    match &ast.statements[0] {
+        Statement::Error(message, pos) => {
+            assert!(message.contains("retry"), "{}", message);
+            assert_eq!(&script[*pos..*pos + 5], "retry");
+        }
+        other => panic!("Expected error statement, got {:?}", other),
+    }
+
+    let ast = script_parser().parse(b"let updated = + retry=2\n| apt-get update\n+\n").unwrap();
+    assert!(matches!(&ast.statements[0], Statement::Assign(name, Expression::Remote(options, _)) if name == "updated" && options.retry == Some(2)));
+}
+
+#[test]
+fn test_parse_remote_block_shell_and_user() {
+    let ast = script_parser().parse(b"+ shell=/bin/sh become_user=postgres\n| echo $0\n+\n").unwrap();
+    match &ast.statements[0] {
+        Statement::Remote(options, _) => {
+            assert_eq!(options.shell, Some("/bin/sh".to_string()));
+            assert_eq!(options.become_user, Some("postgres".to_string()));
+        }
+        _ => panic!("Expected remote block statement"),
+    }
+
+    let ast = script_parser().parse(b"+ shell\n| echo $0\n+\n").unwrap();
+    match &ast.statements[0] {
+        Statement::Error(message, _) => assert!(message.contains("shell")),
+        _ => panic!("Expected error statement"),
+    }
+}
+
+#[test]
+fn test_parse_indented_comments() {
+    let script = "if true {\n    # install\n    | make\n} else {\n  # nothing\n}\nfn build() {\n\t# compile\n}\nsudo {\n    # as root\n}\n";
+    let ast = script_parser().parse(script.as_bytes()).unwrap();
+    assert!(!ast.statements.iter().any(|statement| matches!(statement, Statement::Error(..))), "{:?}", ast.statements);
+    match &ast.statements[0] {
+        Statement::If(branches, Some(otherwise)) => {
+            assert!(branches[0].1.iter().any(|statement| matches!(statement, Statement::Comment())));
+            assert!(otherwise.iter().any(|statement| matches!(statement, Statement::Comment())));
+        }
+        other => panic!("Expected if statement, got {:?}", other),
+    }
+}
+
+#[test]
+fn test_parse_sudo() {
+    let ast = script_parser().parse(b"sudo {\n    + nosudo\n    | make\n    +\n}\n").unwrap();
+    match &ast.statements[0] {
+        Statement::Sudo(statements) => match statements.iter().find(|statement| matches!(statement, Statement::Remote(..))) {
+            Some(Statement::Remote(options, _)) => assert_eq!(options.sudo, Some(false)),
+            _ => panic!("Expected remote block statement"),
+        },
+        _ => panic!("Expected sudo statement"),
+    }
+
+    let ast = script_parser().parse(b"+ sudo nosudo\n| make\n+\n").unwrap();
+    assert!(matches!(&ast.statements[0], Statement::Error(..)));
+}
+
+#[test]
+fn test_parse_remote_assignment() {
+    let script = "let kernel = | uname -r\nlet info = + ignore_errors\n| dpkg -s nginx\n+\n";
+    let ast = script_parser().parse(script.as_bytes()).unwrap();
+    assert_eq!(ast.statements.len(), 2);
+    match &ast.statements[0] {
+        Statement::Assign(name, Expression::Remote(options, lines)) => {
+            assert_eq!(name, "kernel");
+            assert_eq!(options, &BlockOptions::default());
+            assert_eq!(lines, &vec![" uname -r".to_string()]);
+        }
+        _ => panic!("Expected remote assignment"),
+    }
+    match &ast.statements[1] {
+        Statement::Assign(name, Expression::Remote(options, _)) => {
+            assert_eq!(name, "info");
+            assert!(options.ignore_errors);
+        }
+        _ => panic!("Expected remote assignment"),
+    }
+}
+
+#[test]
+fn test_parse_if_else_if_else() {
+    let script = "if $a {\n| echo a\n} else if $b {\n| echo b\n}\nelse {\n| echo c\n}\n";
+    let ast = script_parser().parse(script.as_bytes()).unwrap();
+    match &ast.statements[0] {
+        Statement::If(branches, otherwise) => {
+            assert_eq!(branches.len(), 2);
+            assert!(matches!(&branches[1].0, Expression::Variable(name) if name == "b"));
+            assert!(otherwise.is_some());
+        }
+        _ => panic!("Expected if statement"),
+    }
+}
+
+#[test]
+fn test_literal_truthiness() {
+    assert!(!Literal::String(String::new()).is_truthy());
+    assert!(Literal::String("no".to_string()).is_truthy());
+    assert!(!Literal::Integer(0).is_truthy());
+    assert!(Literal::Integer(-1).is_truthy());
+    assert!(!Literal::Array(vec![]).is_truthy());
+    assert!(Literal::Array(vec![Literal::Void]).is_truthy());
+    assert!(!Literal::Bool(false).is_truthy());
+    assert!(!Literal::Void.is_truthy());
+}
+
+#[test]
+fn test_parse_operator_precedence() {
+    let script = "let ok = $env == \"prod\" && $replicas > 1 + 2 * 3 || !$force\n";
+    let ast = script_parser().parse(script.as_bytes()).unwrap();
+    let Statement::Assign(_, expression) = &ast.statements[0] else {
+        panic!("Expected assignment statement");
+    };
+
+    // ((env == "prod") && (replicas > (1 + (2 * 3)))) || (!force)
+    let Expression::Binary(BinaryOperator::Or, left, right) = expression else {
+        panic!("Expected || at the root, got {:?}", expression);
+    };
+    assert!(matches!(right.as_ref(), Expression::Unary(UnaryOperator::Not, _)));
+
+    let Expression::Binary(BinaryOperator::And, _, comparison) = left.as_ref() else {
+        panic!("Expected && below ||");
+    };
+    let Expression::Binary(BinaryOperator::Greater, _, sum) = comparison.as_ref() else {
+        panic!("Expected > below &&");
+    };
+    let Expression::Binary(BinaryOperator::Add, _, product) = sum.as_ref() else {
+        panic!("Expected + below >");
+    };
+    assert!(matches!(product.as_ref(), Expression::Binary(BinaryOperator::Multiply, _, _)));
+}
+
+#[test]
+fn test_parse_left_associativity() {
+    let script = "let x = 10 - 4 - 3\n";
+    let ast = script_parser().parse(script.as_bytes()).unwrap();
+    let Statement::Assign(_, Expression::Binary(BinaryOperator::Subtract, left, _)) = &ast.statements[0] else {
+        panic!("Expected subtraction");
+    };
+    assert!(matches!(left.as_ref(), Expression::Binary(BinaryOperator::Subtract, _, _)));
+}
+
+#[test]
+fn test_parse_object_and_access() {
+    let script = "let web = {\n    name: \"web\",\n    \"listen port\": 8080,\n}\nlet port = $cfg.servers[0][\"port\"]\n";
+    let ast = script_parser().parse(script.as_bytes()).unwrap();
+    match &ast.statements[0] {
+        Statement::Assign(_, Expression::Object(entries)) => {
+            assert_eq!(entries.len(), 2);
+            assert_eq!(entries[1].0, "listen port");
+        }
+        other => panic!("Expected object assignment, got {:?}", other),
+    }
+    let Statement::Assign(_, Expression::Index(inner, _)) = &ast.statements[2] else {
+        panic!("Expected index access");
+    };
+    let Expression::Index(member, _) = inner.as_ref() else {
+        panic!("Expected index access");
+    };
+    assert!(matches!(member.as_ref(), Expression::Member(_, name) if name == "servers"));
+}
+
+#[test]
+fn test_parse_multiline_array() {
+    let script = "let packages = [\n    \"nginx\",\n    \"git\",\n]\nlet servers = [\n  { name: \"web\" },\n  { name: \"db\" }\n]\n";
+    let ast = script_parser().parse(script.as_bytes()).unwrap();
+    assert!(matches!(&ast.statements[0], Statement::Assign(_, Expression::Array(items)) if items.len() == 2), "{:?}", ast.statements[0]);
+    assert!(matches!(&ast.statements[2], Statement::Assign(_, Expression::Array(items)) if items.len() == 2), "{:?}", ast.statements[2]);
+}
+
+#[test]
+fn test_parse_nested_function_call_expression() {
+    let script = "upload(template(\"nginx.j2\"), \"/etc/nginx/nginx.conf\")\n";
+    let ast = script_parser().parse(script.as_bytes()).unwrap();
+    match &ast.statements[0] {
+        Statement::FnCall(name, args) => {
+            assert_eq!(name, "upload");
+            assert!(matches!(&args[0], Expression::FnCall(inner, inner_args) if inner == "template" && inner_args.len() == 1));
+        }
+        other => panic!("Expected function call, got {:?}", other),
+    }
+}
+
+#[test]
+fn test_parse_function_definition() {
+    let script = "fn add_user(name, groups) {\n    | useradd {{ name }}\n    return $name\n}\n";
+    let ast = script_parser().parse(script.as_bytes()).unwrap();
+    match &ast.statements[0] {
+        Statement::FnDef(name, params, statements) => {
+            assert_eq!(name, "add_user");
+            assert_eq!(params, &vec!["name".to_string(), "groups".to_string()]);
+            assert!(statements.iter().any(|s| matches!(s, Statement::Return(Some(Expression::Variable(_))))));
+        }
+        other => panic!("Expected function definition, got {:?}", other),
+    }
+}
@@ -0,0 +1,127 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Barrier};
+use std::time::Duration;
+
+use seeed::error::SeeedError;
+use seeed::inventory::{Inventory, InventoryHost};
+use seeed::runner::{run_hosts, run_rollout, HostStatus, Serial};
+use seeed::script::ScriptContext;
+use seeed::sshclient::{CommandOutput, RemoteExecutor, RunOptions};
+use seeed::target::Target;
+
+struct MockExecutor;
+
+impl RemoteExecutor for MockExecutor {
+    fn connect(&mut self, _target: &Target) -> Result<(), SeeedError> { Ok(()) }
+    fn command(&self, _command: &str) -> Result<CommandOutput, SeeedError> { Ok(CommandOutput::default()) }
+    fn run(&self, script: &str, _options: &RunOptions) -> Result<CommandOutput, SeeedError> {
+        let exit_code = if script.contains("false") { 1 } else { 0 };
+        Ok(CommandOutput { exit_code, ..CommandOutput::default() })
+    }
+    fn upload(&self, _content: &[u8], _dst_path: String, _options: &RunOptions) -> Result<(), SeeedError> { Ok(()) }
+}
+
+fn hosts(count: usize) -> Vec<InventoryHost> {
+    let contents = (1..=count).map(|i| format!("[hosts.web{}]\ntarget = \"deploy@10.0.0.{}\"\n", i, i)).collect::<String>();
+    Inventory::parse(&contents).unwrap().select(None).unwrap()
+}
+
+fn run_script(script: &str, host: &InventoryHost) -> (usize, Result<(), SeeedError>) {
+    let mut context = ScriptContext::new(Some(host.target.clone()), false, script.to_string(), Box::new(MockExecutor));
+    context.set_variables(&host.variables);
+    let result = context.run(false);
+    (context.changes(), result)
+}
+
+#[test]
+fn test_changes_count() {
+    let host = &hosts(1)[0];
+    let script = "let kernel = | uname -r\n| apt-get update\n+ ignore_errors\n| false\n+\nlet config = <<<EOF\nlisten 80\nEOF>>>\nupload($config, \"/etc/app.conf\")\n";
+    let (changed, result) = run_script(script, host);
+    result.unwrap();
+    // the captured output is not a change
+    assert_eq!(changed, 3);
+}
+
+#[test]
+fn test_run_hosts_reports() {
+    let hosts = hosts(4);
+    let script = "| echo {{ inventory_hostname }}\nif $inventory_hostname == \"web3\" {\n| false\n}\n";
+    let reports = run_hosts(&hosts, 2, |host| run_script(script, host));
+
+    // reports are in the order of the inventory, whatever the order they completed in
+    let names = reports.iter().map(|report| report.name.as_str()).collect::<Vec<_>>();
+    assert_eq!(names, vec!["web1", "web2", "web3", "web4"]);
+
+    assert_eq!(reports[0].status, HostStatus::Ok);
+    assert_eq!(reports[0].changed, 1);
+    assert!(matches!(&reports[2].status, HostStatus::Failed(message) if message.contains("false")));
+    assert_eq!(reports[3].status, HostStatus::Ok);
+}
+
+#[test]
+fn test_run_hosts_in_parallel() {
+    // every worker waits for the others : this only completes if the hosts run concurrently
+    let hosts = hosts(3);
+    let barrier = Arc::new(Barrier::new(3));
+    let reports = run_hosts(&hosts, 3, |_| {
+        barrier.wait();
+        std::thread::sleep(Duration::from_millis(10));
+        (0, Ok(()))
+    });
+    assert_eq!(reports.len(), 3);
+    assert!(reports.iter().all(|report| report.status == HostStatus::Ok));
+}
+
+#[test]
+fn test_serial_parsing() {
+    assert_eq!("2".parse::<Serial>().unwrap(), Serial::Count(2));
+    assert_eq!("25%".parse::<Serial>().unwrap(), Serial::Percent(25));
+    for invalid in ["0", "0%", "101%", "-1", "two", "%"] {
+        assert!(invalid.parse::<Serial>().is_err(), "{} should be rejected", invalid);
+    }
+
+    assert_eq!(Serial::Count(2).batch_size(10), 2);
+    assert_eq!(Serial::Percent(25).batch_size(10), 2);
+    assert_eq!(Serial::Percent(10).batch_size(4), 1);
+    assert_eq!(Serial::Percent(100).batch_size(4), 4);
+}
+
+#[test]
+fn test_rollout_batches() {
+    let hosts = hosts(5);
+    let running = AtomicUsize::new(0);
+    let max_running = AtomicUsize::new(0);
+    let reports = run_rollout(&hosts, 5, Some(Serial::Count(2)), None, |_| {
+        let current = running.fetch_add(1, Ordering::SeqCst) + 1;
+        max_running.fetch_max(current, Ordering::SeqCst);
+        std::thread::sleep(Duration::from_millis(20));
+        running.fetch_sub(1, Ordering::SeqCst);
+        (0, Ok(()))
+    });
+
+    // no more hosts than the batch size run at the same time
+    assert!(max_running.load(Ordering::SeqCst) <= 2);
+    assert_eq!(reports.len(), 5);
+    assert!(reports.iter().all(|report| report.status == HostStatus::Ok));
+}
+
+#[test]
+fn test_rollout_abort() {
+    let hosts = hosts(6);
+    let script = "if $inventory_hostname == \"web1\" || $inventory_hostname == \"web3\" {\n| false\n}\n";
+
+    // 1 failed host out of 6 is below 20%, 2 are above
+    let reports = run_rollout(&hosts, 1, Some(Serial::Count(2)), Some(20), |host| run_script(script, host));
+    let statuses = reports.iter().map(|report| match report.status {
+        HostStatus::Ok => "ok",
+        HostStatus::Failed(_) => "failed",
+        HostStatus::Skipped => "skipped",
+    }).collect::<Vec<_>>();
+    assert_eq!(statuses, vec!["failed", "ok", "failed", "ok", "skipped", "skipped"]);
+    assert_eq!(reports[4].name, "web5");
+
+    // without threshold, every host runs
+    let reports = run_rollout(&hosts, 1, Some(Serial::Count(2)), None, |host| run_script(script, host));
+    assert!(reports.iter().all(|report| report.status != HostStatus::Skipped));
+}
@@ -0,0 +1,70 @@
+use seeed::sshconfig::{wildcard_match, HostConfig, SshConfig};
+use std::path::PathBuf;
+
+#[test]
+fn test_wildcard_match() {
+    assert!(wildcard_match("*", "web1"));
+    assert!(wildcard_match("web?", "web1"));
+    assert!(wildcard_match("*.example.com", "db.prod.example.com"));
+    assert!(wildcard_match("web*db", "web-staging-db"));
+    assert!(!wildcard_match("web?", "web10"));
+    assert!(!wildcard_match("*.example.com", "example.org"));
+}
+
+#[test]
+fn test_host_lookup() {
+    let config = SshConfig::parse("
+# production servers
+Host web1 web2
+    HostName %h.prod.example.com
+    User deploy
+
+Host web* !web3
+    Port 2222
+    ProxyJump admin@bastion:2200
+    IdentityFile /keys/web
+
+Host *
+    User root
+    Port 22
+    IdentityFile=/keys/default
+").unwrap();
+
+    assert_eq!(config.lookup("web1").unwrap(), HostConfig {
+        hostname: Some("web1.prod.example.com".to_string()),
+        user: Some("deploy".to_string()),
+        port: Some(2222),
+        identity_files: vec![PathBuf::from("/keys/web"), PathBuf::from("/keys/default")],
+        proxy_jump: Some("admin@bastion:2200".to_string()),
+    });
+
+    // negated pattern
+    assert_eq!(config.lookup("web3").unwrap(), HostConfig {
+        hostname: None,
+        user: Some("root".to_string()),
+        port: Some(22),
+        identity_files: vec![PathBuf::from("/keys/default")],
+        proxy_jump: None,
+    });
+
+    assert!(SshConfig::parse("Host bad\n  Port ssh\n").unwrap().lookup("bad").is_err());
+}
+
+#[test]
+fn test_include() {
+    let dir = std::env::temp_dir().join(format!("seeed_ssh_config_{}", std::process::id()));
+    std::fs::create_dir_all(dir.join("config.d")).unwrap();
+    std::fs::write(dir.join("config.d/10-db"), "Host db\n    HostName 10.0.0.5\n").unwrap();
+    std::fs::write(dir.join("config.d/20-web"), "Host web\n    HostName 10.0.0.6\n").unwrap();
+    std::fs::write(dir.join("config"), format!("User admin\nInclude {}/config.d/*\n", dir.display())).unwrap();
+
+    let config = SshConfig::load(&dir.join("config")).unwrap();
+    assert_eq!(config.lookup("db").unwrap().hostname, Some("10.0.0.5".to_string()));
+    assert_eq!(config.lookup("web").unwrap().hostname, Some("10.0.0.6".to_string()));
+    assert_eq!(config.lookup("web").unwrap().user, Some("admin".to_string()));
+
+    // a missing file gives an empty configuration
+    assert_eq!(SshConfig::load(&dir.join("missing")).unwrap().lookup("db").unwrap(), HostConfig::default());
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,109 @@
+use seeed::script::parse_script_headers;
+use seeed::sshclient::{expand_home, fingerprint, sudo_command, AuthMethod, HostKeyCheck, Secret, SshOptions};
+use std::io::Write;
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+
+#[test]
+fn test_auth_method_parsing() {
+    assert_eq!("agent".parse::<AuthMethod>(), Ok(AuthMethod::Agent));
+    assert_eq!("key".parse::<AuthMethod>(), Ok(AuthMethod::PublicKey));
+    assert_eq!("password".parse::<AuthMethod>(), Ok(AuthMethod::Password));
+    assert_eq!("keyboard-interactive".parse::<AuthMethod>(), Ok(AuthMethod::KeyboardInteractive));
+    assert!("kerberos".parse::<AuthMethod>().is_err());
+
+    assert_eq!(SshOptions::default().auth_methods, vec![
+        AuthMethod::Agent,
+        AuthMethod::PublicKey,
+        AuthMethod::Password,
+        AuthMethod::KeyboardInteractive,
+    ]);
+}
+
+#[test]
+fn test_connection_headers() {
+    let config = parse_script_headers("# @target: deploy@ci-host\n# @identity: ~/.ssh/deploy_key\n\n| uptime\n").unwrap();
+
+    assert_eq!(config.target, Some("deploy@ci-host".parse().unwrap()));
+    assert_eq!(config.identity, Some("~/.ssh/deploy_key".to_string()));
+    assert_eq!(parse_script_headers("| uptime\n# @identity: ignored\n").unwrap().identity, None);
+
+    let config = parse_script_headers("# @jump: admin@bastion:2200, ops@inner-bastion\n").unwrap();
+    assert_eq!(config.jump, vec!["admin@bastion:2200".parse().unwrap(), "ops@inner-bastion".parse().unwrap()]);
+
+    assert!(parse_script_headers("# @target: root@[::1\n").is_err());
+
+    let config = parse_script_headers("# @shell: /bin/sh\n").unwrap();
+    assert_eq!(config.shell, Some("/bin/sh".to_string()));
+}
+
+#[test]
+fn test_expand_home() {
+    let home = std::env::var("HOME").unwrap();
+
+    assert_eq!(expand_home("~/.ssh/id_rsa"), PathBuf::from(home).join(".ssh/id_rsa"));
+    assert_eq!(expand_home("/etc/keys/id_rsa"), PathBuf::from("/etc/keys/id_rsa"));
+}
+
+#[test]
+fn test_secret_is_not_displayed() {
+    let options = SshOptions {
+        password: Some(Secret::new("hunter2".to_string())),
+        ..SshOptions::default()
+    };
+
+    assert!(!format!("{:?}", options).contains("hunter2"));
+    assert_eq!(options.password.unwrap().expose(), "hunter2");
+}
+
+#[test]
+fn test_host_key_check() {
+    assert_eq!("strict".parse::<HostKeyCheck>(), Ok(HostKeyCheck::Strict));
+    assert_eq!("accept-new".parse::<HostKeyCheck>(), Ok(HostKeyCheck::AcceptNew));
+    assert_eq!("off".parse::<HostKeyCheck>(), Ok(HostKeyCheck::Off));
+    assert!("yes".parse::<HostKeyCheck>().is_err());
+
+    assert_eq!(SshOptions::default().host_key_check, HostKeyCheck::AcceptNew);
+}
+
+#[test]
+fn test_fingerprint_format() {
+    // sha256 of an empty input
+    let hash = [
+        0xe3, 0xb0, 0xc4, 0x42, 0x98, 0xfc, 0x1c, 0x14, 0x9a, 0xfb, 0xf4, 0xc8, 0x99, 0x6f, 0xb9, 0x24,
+        0x27, 0xae, 0x41, 0xe4, 0x64, 0x9b, 0x93, 0x4c, 0xa4, 0x95, 0x99, 0x1b, 0x78, 0x52, 0xb8, 0x55,
+    ];
+
+    assert_eq!(fingerprint(&hash), "SHA256:47DEQpj8HBSa+/TImW+5JCeuQeRkm5NMpJWZG3hSuFU");
+}
+
+#[test]
+fn test_prompts_are_shared() {
+    // the clients of the hosts running in parallel get clones of the same options
+    let options = SshOptions::default();
+    let clone = options.clone();
+    assert!(std::sync::Arc::ptr_eq(&options.prompted, &clone.prompted));
+}
+
+#[test]
+fn test_sudo_command() {
+    assert_eq!(sudo_command(None, false, "make install"), "sudo make install");
+    assert_eq!(
+        sudo_command(Some(&"postgres".to_string()), true, "createdb app"),
+        "sudo -S -k -p '' -u 'postgres' sh -c 'exec < /dev/null; createdb app'"
+    );
+
+    // a sudo that does not ask for the password (NOPASSWD) leaves it unread on the input
+    let command = sudo_command(None, true, "cat");
+    let mut child = Command::new("sh")
+        .arg("-c")
+        .arg(format!("sudo() {{ shift 4; \"$@\"; }}; {}", command))
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child.stdin.take().unwrap().write_all(b"hunter2\n").unwrap();
+    let output = child.wait_with_output().unwrap();
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "");
+}
@@ -1,5 +1,7 @@
 use seeed::script::ScriptContext;
-use seeed::sshclient::RemoteExecutor;
+use seeed::container::ContainerRuntime;
+use seeed::target::Target;
+use seeed::sshclient::{CommandOutput, RemoteExecutor, RunOptions};
 use seeed::error::SeeedError;
 use std::sync::{Arc, Mutex};
 
@@ -20,23 +22,23 @@ impl MockExecutor {
 }
 
 impl RemoteExecutor for MockExecutor {
-    fn connect(&mut self, target: &str) -> Result<(), SeeedError> {
+    fn connect(&mut self, target: &Target) -> Result<(), SeeedError> {
         let mut conn = self.connection_target.lock().unwrap();
         *conn = Some(target.to_string());
         Ok(())
     }
 
-    fn command(&self, command: &str) -> Result<(), SeeedError> {
+    fn command(&self, command: &str) -> Result<CommandOutput, SeeedError> {
         self.commands.lock().unwrap().push(command.to_string());
-        Ok(())
+        Ok(CommandOutput::default())
     }
 
-    fn run(&self, script: &str) -> Result<(), SeeedError> {
+    fn run(&self, script: &str, _options: &RunOptions) -> Result<CommandOutput, SeeedError> {
         self.commands.lock().unwrap().push(format!("RUN: {}", script));
-        Ok(())
+        Ok(CommandOutput::default())
     }
 
-    fn upload(&self, _content: &str, _dst_path: String) -> Result<(), SeeedError> {
+    fn upload(&self, _content: &[u8], _dst_path: String, _options: &RunOptions) -> Result<(), SeeedError> {
         Ok(())
     }
 }
@@ -49,7 +51,7 @@ fn test_target_from_cli() {
     
     // Provide target via CLI arg (simulated)
     let mut context = ScriptContext::new(
-        Some("cli_user@cli_host".to_string()), 
+        Some("cli_user@cli_host".parse().unwrap()), 
         false, 
         script_content.to_string(), 
         executor
@@ -104,3 +106,62 @@ fn test_missing_target_error() {
         _ => panic!("Expected BadTarget error"),
     }
 }
+
+fn target(user: Option<&str>, host: &str, port: Option<u16>) -> Target {
+    Target { user: user.map(str::to_string), host: host.to_string(), port, runtime: None }
+}
+
+#[test]
+fn test_parse_user_and_port() {
+    assert_eq!("root@web1".parse::<Target>().unwrap(), target(Some("root"), "web1", None));
+    assert_eq!("web1:2222".parse::<Target>().unwrap(), target(None, "web1", Some(2222)));
+}
+
+#[test]
+fn test_parse_ipv6_targets() {
+    assert_eq!("root@[2001:db8::1]:2222".parse::<Target>().unwrap(), target(Some("root"), "2001:db8::1", Some(2222)));
+    assert_eq!("root@2001:db8::1".parse::<Target>().unwrap(), target(Some("root"), "2001:db8::1", None));
+    assert_eq!("[::1]".parse::<Target>().unwrap(), target(None, "::1", None));
+    assert_eq!("root@[2001:db8::1]:2222".parse::<Target>().unwrap().to_string(), "root@[2001:db8::1]:2222");
+}
+
+#[test]
+fn test_parse_ssh_urls() {
+    assert_eq!("ssh://deploy@example.com:2200/".parse::<Target>().unwrap(), target(Some("deploy"), "example.com", Some(2200)));
+    assert_eq!("ssh://example.com".parse::<Target>().unwrap(), target(None, "example.com", None));
+}
+
+#[test]
+fn test_container_targets() {
+    let target = "docker://app@web-1".parse::<Target>().unwrap();
+    assert_eq!(target, Target { user: Some("app".to_string()), host: "web-1".to_string(), port: None, runtime: Some(ContainerRuntime::Docker) });
+    assert_eq!(target.to_string(), "docker://app@web-1");
+
+    let target = "podman://ci_runner.2/".parse::<Target>().unwrap();
+    assert_eq!(target.runtime, Some(ContainerRuntime::Podman));
+    assert_eq!(target.host, "ci_runner.2");
+    assert!(!target.is_local());
+
+    for text in ["docker://", "docker://@web", "docker://web:2222", "podman://-web", "docker://web/path"] {
+        assert!(matches!(text.parse::<Target>(), Err(SeeedError::InvalidTarget(_))), "{} should be rejected", text);
+    }
+}
+
+#[test]
+fn test_invalid_targets() {
+    for text in ["", "@host", "root@", "host:ssh", "host:70000", "host:0", "[::1", "[::1]2222", "ssh://host/path", "my host"] {
+        match text.parse::<Target>() {
+            Err(SeeedError::InvalidTarget(_)) => (),
+            other => panic!("{:?}: expected InvalidTarget, got {:?}", text, other),
+        }
+    }
+}
+
+#[test]
+fn test_invalid_target_variable() {
+    let script_content = "let target = \"root@[::1\"\n| echo \"hello\"\n";
+    let executor = Box::new(MockExecutor::new());
+    let mut context = ScriptContext::new(None, false, script_content.to_string(), executor);
+
+    assert!(matches!(context.run(false), Err(SeeedError::InvalidTarget(_))));
+}